@@ -0,0 +1,168 @@
+//! Sliding sprites from tile to tile.
+//!
+//! The game state moves at once, the animation only remembers where the
+//! player and the pushed crate came from and draws them part of the way
+//! there until `duration` has passed. Moves asked for meanwhile wait in
+//! `queue`.
+
+use std::collections::VecDeque;
+
+use sokoban::state::Direction;
+use sokoban::structs::Position;
+
+/// Height of the hop of every step, in tiles.
+const HOP: f64 = 0.06;
+
+#[derive(Clone, Copy)]
+struct Slide {
+    from: [f64; 2],
+    to: Position,
+}
+
+pub struct Animation {
+    /// Seconds a move takes to draw, 0 to jump straight there.
+    pub duration: f64,
+    /// Time into the current move.
+    elapsed: f64,
+    player: Option<Slide>,
+    pushed: Option<Slide>,
+    /// Steps drawn so far, picks the frame of the walk cycle.
+    steps: usize,
+    /// Moves waiting for the current one to finish.
+    pub queue: VecDeque<Direction>,
+}
+
+fn tile(pos: Position) -> [f64; 2] {
+    [pos.get_x() as f64, pos.get_y() as f64]
+}
+
+impl Animation {
+    pub fn new(duration: f64) -> Animation {
+        Animation {
+            duration: duration,
+            elapsed: 0.,
+            player: None,
+            pushed: None,
+            steps: 0,
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Starts drawing a step of the player from `from` to `to`, pushing
+    /// a crate from the first cell of `pushed` to the second.
+    pub fn start(&mut self, from: Position, to: Position, pushed: Option<(Position, Position)>) {
+        if self.duration <= 0. {
+            return;
+        }
+        self.elapsed = 0.;
+        self.player = Some(Slide {
+            from: tile(from),
+            to: to,
+        });
+        self.pushed = pushed.map(|(start, end)| Slide {
+            from: tile(start),
+            to: end,
+        });
+        self.steps += 1;
+    }
+
+    /// Drops the move being drawn and the ones waiting, for undo and
+    /// changing levels.
+    pub fn stop(&mut self) {
+        self.player = None;
+        self.pushed = None;
+        self.queue.clear();
+    }
+
+    pub fn update(&mut self, dt: f64) {
+        if self.player.is_none() {
+            return;
+        }
+        self.elapsed += dt;
+        if self.elapsed >= self.duration {
+            self.player = None;
+            self.pushed = None;
+        }
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.player.is_some()
+    }
+
+    /// Share of the current move done, from 0 to 1.
+    fn done(&self) -> f64 {
+        if self.duration <= 0. {
+            1.
+        } else {
+            (self.elapsed / self.duration).min(1.)
+        }
+    }
+
+    fn at(&self, slide: Option<Slide>, pos: Position) -> [f64; 2] {
+        let to = tile(pos);
+        match slide {
+            Some(slide) if slide.to == pos => {
+                let t = self.done();
+                [
+                    slide.from[0] + (to[0] - slide.from[0]) * t,
+                    slide.from[1] + (to[1] - slide.from[1]) * t,
+                ]
+            }
+            _ => to,
+        }
+    }
+
+    /// Where to draw the player standing at `pos`, in tiles, hopping a
+    /// little with every step.
+    pub fn player(&self, pos: Position) -> [f64; 2] {
+        let mut at = self.at(self.player, pos);
+        if self.player.is_some_and(|s| s.to == pos) {
+            at[1] -= HOP * (self.done() * ::std::f64::consts::PI).sin();
+        }
+        at
+    }
+
+    /// Where to draw a crate standing at `pos`, in tiles.
+    pub fn object(&self, pos: Position) -> [f64; 2] {
+        self.at(self.pushed, pos)
+    }
+
+    /// Frame of a walk cycle of `frames`, two per step.
+    pub fn frame(&self, frames: usize) -> usize {
+        if !self.is_moving() || frames == 0 {
+            return 0;
+        }
+        (self.steps * 2 + (self.done() >= 0.5) as usize) % frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moves_slide_until_their_time_is_up() {
+        let mut animation = Animation::new(0.1);
+        assert!(!animation.is_moving());
+        let pushed = Some((Position::new(2, 1), Position::new(3, 1)));
+        animation.start(Position::new(1, 1), Position::new(2, 1), pushed);
+        assert_eq!(animation.object(Position::new(3, 1)), [2., 1.]);
+        assert_eq!(animation.object(Position::new(5, 5)), [5., 5.]);
+        animation.update(0.05);
+        let player = animation.player(Position::new(2, 1));
+        assert!((player[0] - 1.5).abs() < 1e-9 && player[1] < 1.);
+        assert!((animation.object(Position::new(3, 1))[0] - 2.5).abs() < 1e-9);
+        assert_eq!(animation.frame(4), 3);
+        assert_eq!(animation.frame(1), 0);
+        animation.update(0.06);
+        assert!(!animation.is_moving());
+        assert_eq!(animation.player(Position::new(2, 1)), [2., 1.]);
+    }
+
+    #[test]
+    fn no_duration_jumps_straight_there() {
+        let mut animation = Animation::new(0.);
+        animation.start(Position::new(1, 1), Position::new(2, 1), None);
+        assert!(!animation.is_moving());
+    }
+}
@@ -0,0 +1,127 @@
+//! Which part of the board is on screen and how big it's drawn.
+//!
+//! At zoom 1 the whole board is scaled to fit the window. Zooming in makes
+//! the board bigger than the window, then the view scrolls to keep the
+//! player away from its edges.
+
+/// Smallest and largest zoom, relative to fitting the board.
+const MIN_ZOOM: f64 = 0.5;
+const MAX_ZOOM: f64 = 8.;
+/// Zoom change per key press or wheel step.
+const ZOOM_STEP: f64 = 1.25;
+/// Tiles kept between the player and the edge of the view when scrolling.
+const MARGIN: f64 = 3.;
+
+pub struct Camera {
+    pub zoom: f64,
+    /// Board coordinates (in tiles) at the center of the view.
+    center: [f64; 2],
+}
+
+impl Camera {
+    pub fn new() -> Camera {
+        Camera {
+            zoom: 1.,
+            center: [0., 0.],
+        }
+    }
+
+    pub fn zoom_in(&mut self) {
+        self.zoom = (self.zoom * ZOOM_STEP).min(MAX_ZOOM);
+    }
+
+    pub fn zoom_out(&mut self) {
+        self.zoom = (self.zoom / ZOOM_STEP).max(MIN_ZOOM);
+    }
+
+    /// Zooms by mouse wheel `steps`, positive zooms in.
+    pub fn scroll(&mut self, steps: f64) {
+        self.zoom = (self.zoom * ZOOM_STEP.powf(steps)).clamp(MIN_ZOOM, MAX_ZOOM);
+    }
+
+    /// Back to fitting the whole board.
+    pub fn reset(&mut self) {
+        self.zoom = 1.;
+    }
+
+    /// Pixels per tile for a `board` (width, height) shown in `view`
+    /// (width, height in pixels).
+    pub fn tile_size(&self, view: [f64; 2], board: (usize, usize)) -> f64 {
+        let fit = (view[0] / board.0.max(1) as f64).min(view[1] / board.1.max(1) as f64);
+        fit * self.zoom
+    }
+
+    /// Scrolls so `player` (in tiles) stays in view. Axes where the board
+    /// fits are centered instead.
+    pub fn follow(&mut self, player: [f64; 2], view: [f64; 2], board: (usize, usize)) {
+        let tile = self.tile_size(view, board);
+        let board = [board.0 as f64, board.1 as f64];
+        for axis in 0..2 {
+            // tiles visible along this axis
+            let span = view[axis] / tile;
+            if span >= board[axis] {
+                self.center[axis] = board[axis] / 2.;
+                continue;
+            }
+            let player = player[axis] + 0.5;
+            let margin = MARGIN.min(span / 2.);
+            let low = self.center[axis] - span / 2. + margin;
+            let high = self.center[axis] + span / 2. - margin;
+            if player < low {
+                self.center[axis] -= low - player;
+            } else if player > high {
+                self.center[axis] += player - high;
+            }
+            self.center[axis] = self.center[axis]
+                .max(span / 2.)
+                .min(board[axis] - span / 2.);
+        }
+    }
+
+    /// Pixel position in `view` of the top-left corner of tile `x`, `y`.
+    pub fn to_screen(&self, x: f64, y: f64, view: [f64; 2], board: (usize, usize)) -> [f64; 2] {
+        let tile = self.tile_size(view, board);
+        [
+            view[0] / 2. + (x - self.center[0]) * tile,
+            view[1] / 2. + (y - self.center[1]) * tile,
+        ]
+    }
+
+    /// Board coordinates (in tiles) under pixel `screen` of `view`, the
+    /// inverse of `to_screen`.
+    pub fn to_board(&self, screen: [f64; 2], view: [f64; 2], board: (usize, usize)) -> [f64; 2] {
+        let tile = self.tile_size(view, board);
+        [
+            self.center[0] + (screen[0] - view[0] / 2.) / tile,
+            self.center[1] + (screen[1] - view[1] / 2.) / tile,
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_boards_fit_the_view() {
+        let mut camera = Camera::new();
+        let view = [960., 640.];
+        camera.follow([1., 1.], view, (15, 10));
+        assert_eq!(camera.tile_size(view, (15, 10)), 64.);
+        assert_eq!(camera.to_screen(0., 0., view, (15, 10)), [0., 0.]);
+    }
+
+    #[test]
+    fn the_player_stays_in_view_when_zoomed_in() {
+        let mut camera = Camera::new();
+        let (view, board) = ([960., 640.], (100, 60));
+        camera.zoom = 4.;
+        for &(x, y) in [(0., 0.), (50., 30.), (99., 59.), (10., 50.)].iter() {
+            camera.follow([x, y], view, board);
+            let at = camera.to_screen(x, y, view, board);
+            let tile = camera.tile_size(view, board);
+            assert!(at[0] >= 0. && at[0] + tile <= view[0] + 1e-6);
+            assert!(at[1] >= 0. && at[1] + tile <= view[1] + 1e-6);
+        }
+    }
+}
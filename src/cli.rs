@@ -0,0 +1,338 @@
+//! Command line options.
+
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: sokoban [OPTIONS] [PACK]
+       sokoban generate [OPTIONS]
+       sokoban solve [OPTIONS] FILE
+       sokoban verify [OPTIONS] FILE
+
+Commands, run without opening a window:
+    generate            print generated levels, --count of them
+    solve               print a solution of every level in FILE, or of --index
+    verify              check the Solution lines of the levels in FILE
+
+Options:
+    --level FILE        play a single level of FILE
+    --pack FILE         play the levels of FILE in order, saving progress
+    --index N           level of the file to play or solve, from 1
+    --replay FILE       check and play back the replay in FILE
+    --edit FILE         build level --index of FILE in the editor, a new one if there's none
+    --seed N            seed of the generated level, the first of --count
+    --width N           width of generated levels [15]
+    --height N          height of generated levels [10]
+    --crates MIN..MAX   crates on generated levels [3..8]
+    --colors LIST       crate colors of generated levels, e.g. red,blue
+    --tile-size N       pixels per tile of the window when it opens [64]
+    --move-time N       milliseconds a move takes to draw in the window, 0 to jump [120]
+    --assets DIR        where textures and fonts are read from [assets]
+    --limit N           positions the solver may look at [200000]
+    --scoring FILE      scoring rules [scoring.txt in the data directory]
+    --classic           crates on their target can be pushed off again
+    --monochrome        any crate fits any target, levels are played without colors
+    -h, --help          print this help
+
+Generate options:
+    --count N           levels to generate [1]
+    --output FILE       write the levels to FILE instead of printing them
+    --solvable          keep only levels the solver solves within --limit
+    --min-pushes N      keep only levels needing at least N pushes
+    --min-moves N       keep only levels whose solution takes at least N moves";
+
+/// Options taking a value.
+const OPTIONS: &[&str] = &[
+    "--level",
+    "--pack",
+    "--index",
+    "--replay",
+    "--edit",
+    "--seed",
+    "--width",
+    "--height",
+    "--crates",
+    "--colors",
+    "--tile-size",
+    "--move-time",
+    "--assets",
+    "--limit",
+    "--scoring",
+    "--count",
+    "--output",
+    "--min-pushes",
+    "--min-moves",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    Play,
+    Generate,
+    Solve,
+    Verify,
+    Help,
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub command: Command,
+    /// Level or pack file.
+    pub file: Option<String>,
+    /// Whether `file` is played as a pack, every level in order.
+    pub pack: bool,
+    /// Level of `file`, from 0.
+    pub index: Option<usize>,
+    pub replay: Option<String>,
+    /// File of the level opened in the editor.
+    pub edit: Option<String>,
+    pub seed: Option<u64>,
+    pub size: (usize, usize),
+    pub crates: Option<(usize, usize)>,
+    /// Crate texture names, e.g. "red.png".
+    pub colors: Option<Vec<String>>,
+    pub tile_size: u32,
+    /// Milliseconds a move takes to draw.
+    pub move_time: u32,
+    pub assets: PathBuf,
+    pub limit: usize,
+    pub scoring: Option<String>,
+    /// Play by classic rules, crates don't lock on their targets.
+    pub classic: bool,
+    /// Ignore crate colors when loading and generating levels.
+    pub monochrome: bool,
+    pub count: usize,
+    pub output: Option<String>,
+    pub solvable: bool,
+    pub min_pushes: Option<usize>,
+    pub min_moves: Option<usize>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            command: Command::Play,
+            file: None,
+            pack: true,
+            index: None,
+            replay: None,
+            edit: None,
+            seed: None,
+            size: (15, 10),
+            crates: None,
+            colors: None,
+            tile_size: 64,
+            move_time: 120,
+            assets: PathBuf::from("assets"),
+            limit: 200_000,
+            scoring: None,
+            classic: false,
+            monochrome: false,
+            count: 1,
+            output: None,
+            solvable: false,
+            min_pushes: None,
+            min_moves: None,
+        }
+    }
+}
+
+fn number<T: ::std::str::FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} takes a number, not '{}'", option, value))
+}
+
+/// "3..8", or "5" for exactly 5.
+fn range(option: &str, value: &str) -> Result<(usize, usize), String> {
+    let (min, max) = match value.find("..") {
+        Some(i) => (
+            number(option, &value[..i])?,
+            number(option, &value[i + 2..])?,
+        ),
+        None => {
+            let n = number(option, value)?;
+            (n, n)
+        }
+    };
+    if min == 0 || min > max {
+        return Err(format!(
+            "{} takes a range like 3..8, not '{}'",
+            option, value
+        ));
+    }
+    Ok((min, max))
+}
+
+/// "red,blue" to "red.png", "blue.png".
+fn colors(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|c| c.trim())
+        .filter(|c| !c.is_empty())
+        .map(|c| {
+            if c.contains('.') {
+                c.to_string()
+            } else {
+                format!("{}.png", c)
+            }
+        })
+        .collect()
+}
+
+/// Reads the arguments after the program name.
+pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut opts = Options::default();
+    let mut first = true;
+    while let Some(arg) = args.next() {
+        let (option, inline) = match arg.find('=') {
+            Some(i) if arg.starts_with("--") => {
+                (arg[..i].to_string(), Some(arg[i + 1..].to_string()))
+            }
+            _ => (arg.clone(), None),
+        };
+        if !option.starts_with('-') {
+            match option.as_str() {
+                "generate" if first => opts.command = Command::Generate,
+                "solve" if first => opts.command = Command::Solve,
+                "verify" if first => opts.command = Command::Verify,
+                _ if opts.file.is_none() => opts.file = Some(arg),
+                _ => return Err(format!("unexpected argument '{}'", arg)),
+            }
+            first = false;
+            continue;
+        }
+        first = false;
+        if option == "-h" || option == "--help" {
+            opts.command = Command::Help;
+            return Ok(opts);
+        }
+        if option == "--solvable" {
+            opts.solvable = true;
+            continue;
+        }
+        if option == "--classic" {
+            opts.classic = true;
+            continue;
+        }
+        if option == "--monochrome" {
+            opts.monochrome = true;
+            continue;
+        }
+        if !OPTIONS.contains(&option.as_str()) {
+            return Err(format!("unknown option '{}'", option));
+        }
+        let value = match inline.or_else(|| args.next()) {
+            Some(v) => v,
+            None => return Err(format!("{} needs a value", option)),
+        };
+        match option.as_str() {
+            "--level" => {
+                opts.file = Some(value);
+                opts.pack = false;
+            }
+            "--pack" => {
+                opts.file = Some(value);
+                opts.pack = true;
+            }
+            "--index" => {
+                let index: usize = number(&option, &value)?;
+                if index == 0 {
+                    return Err("--index counts from 1".to_string());
+                }
+                opts.index = Some(index - 1);
+            }
+            "--replay" => opts.replay = Some(value),
+            "--edit" => opts.edit = Some(value),
+            "--seed" => opts.seed = Some(number(&option, &value)?),
+            "--width" => opts.size.0 = number(&option, &value)?,
+            "--height" => opts.size.1 = number(&option, &value)?,
+            "--crates" => opts.crates = Some(range(&option, &value)?),
+            "--colors" => {
+                let colors = colors(&value);
+                if colors.is_empty() {
+                    return Err("--colors needs at least one color".to_string());
+                }
+                opts.colors = Some(colors);
+            }
+            "--tile-size" => opts.tile_size = number(&option, &value)?,
+            "--move-time" => opts.move_time = number(&option, &value)?,
+            "--assets" => opts.assets = PathBuf::from(value),
+            "--limit" => opts.limit = number(&option, &value)?,
+            "--scoring" => opts.scoring = Some(value),
+            "--count" => opts.count = number(&option, &value)?,
+            "--output" => opts.output = Some(value),
+            "--min-pushes" => opts.min_pushes = Some(number(&option, &value)?),
+            "--min-moves" => opts.min_moves = Some(number(&option, &value)?),
+            _ => unreachable!(),
+        }
+    }
+    if opts.size.0 < 5 || opts.size.1 < 5 {
+        return Err("levels need a width and height of at least 5".to_string());
+    }
+    if opts.tile_size == 0 {
+        return Err("--tile-size can't be 0".to_string());
+    }
+    match opts.command {
+        Command::Solve | Command::Verify if opts.file.is_none() => {
+            Err("solve and verify need a level file".to_string())
+        }
+        _ => Ok(opts),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Result<Options, String> {
+        parse(line.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn subcommands_take_a_file_and_options() {
+        let opts = args("solve levels.txt --index 2 --limit=500").unwrap();
+        assert_eq!(opts.command, Command::Solve);
+        assert_eq!(opts.file.as_deref(), Some("levels.txt"));
+        assert_eq!(opts.index, Some(1));
+        assert_eq!(opts.limit, 500);
+        let opts = args("generate --seed 7 --crates 2..4 --colors red,blue.png").unwrap();
+        assert_eq!(opts.command, Command::Generate);
+        assert_eq!(opts.seed, Some(7));
+        assert_eq!(opts.crates, Some((2, 4)));
+        assert_eq!(
+            opts.colors,
+            Some(vec!["red.png".to_string(), "blue.png".to_string()])
+        );
+    }
+
+    #[test]
+    fn generate_takes_export_filters() {
+        let opts = args("generate --count 20 --solvable --min-pushes 8 --output out.txt").unwrap();
+        assert_eq!(opts.count, 20);
+        assert!(opts.solvable);
+        assert_eq!(opts.min_pushes, Some(8));
+        assert_eq!(opts.min_moves, None);
+        assert_eq!(opts.output.as_deref(), Some("out.txt"));
+    }
+
+    #[test]
+    fn play_opens_a_pack_or_a_level() {
+        let opts = args("--pack pack.txt").unwrap();
+        assert_eq!(opts.command, Command::Play);
+        assert!(opts.pack);
+        let opts = args("--level one.txt --index 1").unwrap();
+        assert!(!opts.pack);
+        assert_eq!(opts.index, Some(0));
+        assert_eq!(args("-h").unwrap().command, Command::Help);
+    }
+
+    #[test]
+    fn bad_arguments_are_errors() {
+        assert!(args("--index 0 --level a.txt").is_err());
+        assert!(args("--crates 5..3").is_err());
+        assert!(args("--width 3").is_err());
+        assert!(args("--seed").is_err());
+        assert!(args("--bogus 1").is_err());
+        assert!(args("solve").is_err());
+        assert!(args("a.txt b.txt").is_err());
+    }
+}
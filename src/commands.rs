@@ -0,0 +1,249 @@
+//! Commands run from the terminal, without a window or textures, and the
+//! setup shared by both front ends.
+
+use std::fs;
+
+use cli::Options;
+use level::{load_collection, save_collection, write_collection, Level, DEFAULT_COLOR};
+use pack::{Pack, Session};
+use replay::{Replay, Verdict};
+use scoring::ScoringRules;
+use solver::solve;
+use state::{GameState, RuleSet};
+
+/// Generated levels tried per level asked for before `generate` gives up
+/// on the filters.
+const TRIES_PER_LEVEL: usize = 50;
+
+/// Crate colors to generate with, the ones asked for or every crate
+/// texture under `assets`.
+pub fn colors(opts: &Options) -> Vec<String> {
+    if let Some(ref colors) = opts.colors {
+        return colors.clone();
+    }
+    let mut colors: Vec<String> = fs::read_dir(opts.assets.join("crates"))
+        .map(|dir| {
+            dir.filter_map(|e| e.ok())
+                .filter_map(|e| e.file_name().into_string().ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    if colors.is_empty() {
+        colors.push(DEFAULT_COLOR.to_string());
+    }
+    colors
+}
+
+/// A state set up from the generator options and rules.
+pub fn new_state(opts: &Options) -> GameState {
+    let mut state = GameState::new(opts.size, colors(opts));
+    if let Some(crates) = opts.crates {
+        state.generator.crates = crates;
+    }
+    if opts.classic {
+        state.rule_set = RuleSet::Classic;
+    }
+    state.monochrome = opts.monochrome;
+    state
+}
+
+/// Scoring rules of `--scoring`, or the ones in the data directory.
+pub fn rules(opts: &Options) -> Result<ScoringRules, String> {
+    match opts.scoring {
+        Some(ref path) => ScoringRules::load(path).map_err(|e| format!("{}: {}", path, e)),
+        None => ScoringRules::load_default().map_err(|e| format!("scoring rules: {}", e)),
+    }
+}
+
+/// Plays the file of `--pack` through `session`, or level `--index` of
+/// the file of `--level` on its own. Returns a message for the player if
+/// there's one.
+pub fn open_file(
+    opts: &Options,
+    session: &mut Session,
+    state: &mut GameState,
+) -> Result<Option<String>, String> {
+    let path = opts.file.as_ref().unwrap();
+    let pack = Pack::open(path).map_err(|e| format!("could not load {}: {}", path, e))?;
+    check_index(opts, pack.levels.len())?;
+    if opts.pack {
+        return Ok(session.open_pack(pack, opts.index, state));
+    }
+    state.load_level(&pack.levels[opts.index.unwrap_or(0)]);
+    Ok(None)
+}
+
+/// Fails if `--index` is past the `count` levels of the file.
+fn check_index(opts: &Options, count: usize) -> Result<(), String> {
+    match opts.index {
+        Some(i) if i >= count => Err(format!(
+            "{} has {} levels, not {}",
+            opts.file.as_ref().unwrap(),
+            count,
+            i + 1
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Levels of `opts.file`, only `--index` if given, numbered from 0.
+fn levels(opts: &Options) -> Result<Vec<(usize, Level)>, String> {
+    let path = opts.file.as_ref().unwrap();
+    let levels = load_collection(path).map_err(|e| format!("{}: {}", path, e))?;
+    check_index(opts, levels.len())?;
+    let levels = levels.into_iter().enumerate();
+    match opts.index {
+        Some(i) => Ok(levels.skip(i).take(1).collect()),
+        None => Ok(levels.collect()),
+    }
+}
+
+fn generate(opts: &Options) -> Result<(), String> {
+    let mut state = new_state(opts);
+    let filtered = opts.solvable || opts.min_pushes.is_some() || opts.min_moves.is_some();
+    let mut levels = Vec::new();
+    let mut tries = 0;
+    while levels.len() < opts.count {
+        if tries == opts.count * TRIES_PER_LEVEL {
+            return Err(format!(
+                "only {} of {} levels passed after {} tries",
+                levels.len(),
+                opts.count,
+                tries
+            ));
+        }
+        let metrics = match opts.seed {
+            Some(seed) => state.gen_seeded(seed.wrapping_add(tries as u64)),
+            None => state.gen_level(),
+        };
+        tries += 1;
+        let mut level = state.level.clone().unwrap();
+        level.notes.push(format!(
+            "; at least {} pushes, {} box lines, {} box changes from solved",
+            metrics.pushes, metrics.box_lines, metrics.box_changes
+        ));
+        if filtered {
+            let solution = match solve(&state, opts.limit) {
+                Ok(solution) => solution,
+                Err(_) => continue,
+            };
+            if opts.min_pushes.is_some_and(|n| solution.pushes.len() < n)
+                || opts.min_moves.is_some_and(|n| solution.moves.len() < n)
+            {
+                continue;
+            }
+            level.notes.push(format!(
+                "; solved in {} moves, {} pushes",
+                solution.moves.len(),
+                solution.pushes.len()
+            ));
+        }
+        level.title = Some(format!("Generated {}", levels.len() + 1));
+        eprintln!(
+            "{}/{} (seed {})",
+            levels.len() + 1,
+            opts.count,
+            state.seed.unwrap()
+        );
+        levels.push(level);
+    }
+    match opts.output {
+        Some(ref path) => save_collection(path, &levels).map_err(|e| format!("{}: {}", path, e)),
+        None => {
+            print!("{}", write_collection(&levels));
+            Ok(())
+        }
+    }
+}
+
+fn solve_all(opts: &Options) -> Result<(), String> {
+    let mut state = new_state(opts);
+    let mut unsolved = 0;
+    for (i, level) in levels(opts)? {
+        state.load_level(&level);
+        match solve(&state, opts.limit) {
+            Ok(solution) => {
+                for dir in solution.moves {
+                    state.move_player(dir);
+                }
+                println!("{}", Replay::record(&state).unwrap().to_level());
+            }
+            Err(e) => {
+                eprintln!("level {}: {}", i + 1, e);
+                unsolved += 1;
+            }
+        }
+    }
+    if unsolved > 0 {
+        return Err(format!("{} levels not solved", unsolved));
+    }
+    Ok(())
+}
+
+fn verify(opts: &Options) -> Result<(), String> {
+    let mut state = new_state(opts);
+    let mut checked = 0;
+    let mut failed = 0;
+    for (i, level) in levels(opts)? {
+        if level.meta("Solution").is_none() {
+            continue;
+        }
+        checked += 1;
+        let verdict = Replay::from_level(&level).and_then(|r| r.verify(&mut state));
+        match verdict {
+            Ok(Verdict::Solved) => println!("level {}: solved in {}", i + 1, state.score),
+            Ok(Verdict::Unsolved) => {
+                println!("level {}: not solved", i + 1);
+                failed += 1;
+            }
+            Err(e) => {
+                println!("level {}: {}", i + 1, e);
+                failed += 1;
+            }
+        }
+    }
+    if checked == 0 {
+        return Err("no level with a Solution line".to_string());
+    }
+    if failed > 0 {
+        return Err(format!("{} of {} solutions failed", failed, checked));
+    }
+    Ok(())
+}
+
+/// Runs the command of `opts`, returns the exit code.
+pub fn run(opts: &Options) -> i32 {
+    use cli::Command::*;
+    let result = match opts.command {
+        Generate => generate(opts),
+        Solve => solve_all(opts),
+        Verify => verify(opts),
+        Play | Help => Ok(()),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_index_names_the_levels_there_are() {
+        let opts = Options {
+            file: Some("pack.txt".to_string()),
+            index: Some(3),
+            ..Options::default()
+        };
+        assert_eq!(
+            check_index(&opts, 3),
+            Err("pack.txt has 3 levels, not 4".to_string())
+        );
+        assert_eq!(check_index(&opts, 4), Ok(()));
+    }
+}
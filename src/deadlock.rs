@@ -0,0 +1,194 @@
+//! Spotting positions that can't be solved anymore.
+//!
+//! A crate is stuck when it can't reach any free target of its color
+//! (corners and other dead squares), or when it's frozen in place by walls
+//! and other crates, including 2x2 blocks. The position is deadlocked once
+//! some color is left with fewer crates that can still move than free
+//! targets, or those crates can't each get a target of their own. Under
+//! classic rules a frozen crate is fine as long as it's on its own target.
+//! Boards with special tiles aren't checked, a hole or a teleporter frees
+//! crates that look stuck.
+
+use solver::{Board, INF, MATCHING_LIMIT};
+use state::{Direction, GameState};
+use structs::Position;
+
+#[derive(Clone, Debug)]
+pub struct Deadlock {
+    /// Crates causing the deadlock.
+    pub crates: Vec<Position>,
+}
+
+fn is_crate(crates: &[(usize, u8)], cell: usize) -> bool {
+    crates.iter().any(|c| c.0 == cell)
+}
+
+/// Whether a crate at `cell` can't move along `axis`.
+/// Cells in `held` are crates already assumed frozen.
+fn blocked_axis(
+    board: &Board,
+    crates: &[(usize, u8)],
+    cell: usize,
+    axis: [Direction; 2],
+    held: &mut Vec<usize>,
+) -> bool {
+    let neighbors: Vec<Option<usize>> = axis.iter().map(|d| board.step(cell, *d)).collect();
+    for n in neighbors.iter() {
+        match *n {
+            None => return true,
+            Some(n) if board.wall[n] || held.contains(&n) => return true,
+            _ => (),
+        }
+    }
+    for n in neighbors.into_iter().flatten() {
+        if is_crate(crates, n) {
+            held.push(cell);
+            let frozen = frozen(board, crates, n, held);
+            held.pop();
+            if frozen {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+fn frozen(board: &Board, crates: &[(usize, u8)], cell: usize, held: &mut Vec<usize>) -> bool {
+    blocked_axis(
+        board,
+        crates,
+        cell,
+        [Direction::Left, Direction::Right],
+        held,
+    ) && blocked_axis(board, crates, cell, [Direction::Up, Direction::Down], held)
+}
+
+/// Whether `cell` is part of a 2x2 square of walls and crates.
+fn in_block(board: &Board, crates: &[(usize, u8)], cell: usize) -> bool {
+    let solid = |c: Option<usize>| match c {
+        Some(c) => board.wall[c] || is_crate(crates, c),
+        None => true,
+    };
+    let corners = [
+        (Direction::Up, Direction::Left),
+        (Direction::Up, Direction::Right),
+        (Direction::Down, Direction::Left),
+        (Direction::Down, Direction::Right),
+    ];
+    corners.iter().any(|&(v, h)| {
+        let a = board.step(cell, v);
+        let b = board.step(cell, h);
+        let diagonal = a.and_then(|a| board.step(a, h));
+        solid(a) && solid(b) && solid(diagonal)
+    })
+}
+
+pub fn find(state: &GameState) -> Option<Deadlock> {
+    if state.targets_left <= 0 || state.has_special_tiles() {
+        return None;
+    }
+    let (board, crates, _) = Board::new(state);
+
+    let stuck: Vec<bool> = crates
+        .iter()
+        .map(|&(cell, color)| {
+            let targets: Vec<usize> = (0..board.targets.len())
+                .filter(|t| board.targets[*t].1 == color)
+                .collect();
+            if targets.is_empty() {
+                // a spare crate, it never has to move
+                return false;
+            }
+            if board.on_target(cell, color) {
+                // only ever the case under classic rules
+                return false;
+            }
+            targets.iter().all(|t| board.dist[*t][cell] == INF)
+                || frozen(&board, &crates, cell, &mut Vec::new())
+                || in_block(&board, &crates, cell)
+        })
+        .collect();
+
+    let mut colors: Vec<u8> = board.targets.iter().map(|t| t.1).collect();
+    colors.sort();
+    colors.dedup();
+    let mut offending = Vec::new();
+    let mut deadlocked = false;
+    for color in colors {
+        let targets: Vec<usize> = (0..board.targets.len())
+            .filter(|t| board.targets[*t].1 == color)
+            .collect();
+        let of_color: Vec<usize> = (0..crates.len())
+            .filter(|i| crates[*i].1 == color)
+            .collect();
+        let live: Vec<usize> = of_color
+            .iter()
+            .filter(|i| !stuck[**i])
+            .map(|i| crates[*i].0)
+            .collect();
+        let short = live.len() < targets.len()
+            || (live.len() <= MATCHING_LIMIT && board.matching(&targets, &live) == INF);
+        if !short {
+            continue;
+        }
+        deadlocked = true;
+        let culprits: Vec<usize> = of_color.iter().cloned().filter(|i| stuck[*i]).collect();
+        let culprits = if culprits.is_empty() {
+            of_color
+        } else {
+            culprits
+        };
+        offending.extend(culprits.into_iter().map(|i| board.position(crates[i].0)));
+    }
+
+    if deadlocked {
+        Some(Deadlock { crates: offending })
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use level::Level;
+
+    fn stuck(text: &str) -> Option<usize> {
+        let mut state = GameState::new((15, 10), Vec::new());
+        state.load_level(&Level::parse(text).unwrap());
+        find(&state).map(|d| d.crates.len())
+    }
+
+    #[test]
+    fn free_crates_are_fine() {
+        assert_eq!(stuck("######\n#@ $.#\n######\n"), None);
+        assert_eq!(stuck("######\n# $ .#\n#@   #\n######\n"), None);
+    }
+
+    #[test]
+    fn crates_in_a_corner_are_stuck() {
+        assert_eq!(stuck("#####\n#$ .#\n#@  #\n#####\n"), Some(1));
+    }
+
+    #[test]
+    fn crates_on_a_wall_without_a_target_are_stuck() {
+        assert_eq!(stuck("######\n# $  #\n#@   #\n#  . #\n######\n"), Some(1));
+    }
+
+    #[test]
+    fn four_crates_in_a_square_are_stuck() {
+        let text = "########\n#@     #\n# $$   #\n# $$   #\n# .... #\n########\n";
+        assert_eq!(stuck(text), Some(4));
+    }
+
+    #[test]
+    fn state_notices_a_push_into_a_corner() {
+        let mut state = GameState::new((15, 10), Vec::new());
+        state.load_level(&Level::parse("#####\n#   #\n# $ #\n# @ #\n#. ##\n#####\n").unwrap());
+        assert!(state.deadlock.is_none());
+        state.move_player(Direction::Up);
+        assert!(state.deadlock.is_some());
+        state.undo();
+        assert!(state.deadlock.is_none());
+    }
+}
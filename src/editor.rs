@@ -0,0 +1,577 @@
+//! Building levels by hand.
+//!
+//! The editor keeps the board as cells that can hold a crate and a target
+//! of any color at once, so painting never loses anything by surprise, and
+//! turns it into a `Level` for playing and saving. `problems` tells what
+//! keeps the board from being a playable level, and such a board isn't
+//! saved, as one bad level would keep the whole file from loading.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use level::{load_collection, save_collection, Level, LevelError, Tile, DEFAULT_COLOR};
+use tiles;
+
+/// Boards can't be made smaller than this or larger than `MAX_SIZE`.
+const MIN_SIZE: usize = 3;
+const MAX_SIZE: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ground {
+    /// Blank space around the walls.
+    Outside,
+    Floor,
+    Wall,
+    /// A special tile by its symbol, never holding a crate, target or the
+    /// player.
+    Tile(char),
+}
+
+/// One square of the board being edited.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cell {
+    pub ground: Ground,
+    /// Color of the crate standing here, e.g. "red.png".
+    pub crate_color: Option<String>,
+    /// Color of the target here.
+    pub goal_color: Option<String>,
+    /// Color of the special tile here, for those that come in colors.
+    pub tile_color: Option<String>,
+}
+
+/// What a click paints.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Brush {
+    Wall,
+    /// Floor, clearing whatever stood there.
+    Floor,
+    Crate(String),
+    Target(String),
+    Player,
+    /// A special tile by its symbol, with its color if it comes in colors.
+    Tile(char, Option<String>),
+    /// Back to blank space.
+    Erase,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Level(LevelError),
+    /// The board isn't a playable level yet, the first of its `problems`.
+    Unplayable(String),
+}
+
+pub struct Editor {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+    player: Option<(usize, usize)>,
+    title: Option<String>,
+    notes: Vec<String>,
+    /// File and index in it the level is saved to.
+    pub file: Option<(PathBuf, usize)>,
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SaveError::Level(ref e) => write!(f, "{}", e),
+            SaveError::Unplayable(ref problem) => write!(f, "not playable yet: {}", problem),
+        }
+    }
+}
+
+impl Error for SaveError {}
+
+impl From<LevelError> for SaveError {
+    fn from(e: LevelError) -> SaveError {
+        SaveError::Level(e)
+    }
+}
+
+impl Cell {
+    fn new(ground: Ground) -> Cell {
+        Cell {
+            ground: ground,
+            crate_color: None,
+            goal_color: None,
+            tile_color: None,
+        }
+    }
+
+    /// Turns the cell to floor, dropping the special tile and its color if
+    /// there was one.
+    fn set_floor(&mut self) {
+        self.ground = Ground::Floor;
+        self.tile_color = None;
+    }
+}
+
+impl Brush {
+    pub fn name(&self) -> String {
+        let color = |c: &str| c.trim_end_matches(".png").to_string();
+        match *self {
+            Brush::Wall => "wall".to_string(),
+            Brush::Floor => "floor".to_string(),
+            Brush::Crate(ref c) => format!("{} crate", color(c)),
+            Brush::Target(ref c) => format!("{} target", color(c)),
+            Brush::Player => "player".to_string(),
+            Brush::Tile(symbol, ref c) => {
+                let name = tiles::by_symbol(symbol)
+                    .map_or("tile", |t| t.sprite())
+                    .replace('_', " ");
+                match *c {
+                    Some(ref c) => format!("{} {}", color(c), name),
+                    None => name,
+                }
+            }
+            Brush::Erase => "eraser".to_string(),
+        }
+    }
+}
+
+impl Editor {
+    /// An empty room of `size`, floor surrounded by walls.
+    pub fn new(size: (usize, usize)) -> Editor {
+        let (width, height) = (
+            size.0.clamp(MIN_SIZE, MAX_SIZE),
+            size.1.clamp(MIN_SIZE, MAX_SIZE),
+        );
+        let mut cells = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let border = x == 0 || y == 0 || x + 1 == width || y + 1 == height;
+                cells.push(Cell::new(if border { Ground::Wall } else { Ground::Floor }));
+            }
+        }
+        Editor {
+            width: width,
+            height: height,
+            cells: cells,
+            player: None,
+            title: None,
+            notes: Vec::new(),
+            file: None,
+        }
+    }
+
+    pub fn from_level(level: &Level) -> Editor {
+        let mut editor = Editor::new((level.width(), level.height()));
+        let (mut crates, mut goals, mut paints) = (0, 0, 0);
+        for y in 0..editor.height {
+            for x in 0..editor.width {
+                let tile = level.get(x, y);
+                let cell = &mut editor.cells[y * editor.width + x];
+                *cell = Cell::new(match tile {
+                    Tile::Outside => Ground::Outside,
+                    Tile::Wall => Ground::Wall,
+                    Tile::Special(symbol) => Ground::Tile(symbol),
+                    _ => Ground::Floor,
+                });
+                if let Tile::Special(symbol) = tile {
+                    if tiles::by_symbol(symbol).is_some_and(|t| t.colored()) {
+                        cell.tile_color = Some(level.paint_color(paints).to_string());
+                        paints += 1;
+                    }
+                }
+                if tile.is_crate() {
+                    cell.crate_color = Some(level.crate_color(crates).to_string());
+                    crates += 1;
+                }
+                if tile.is_goal() {
+                    cell.goal_color = Some(level.goal_color(goals).to_string());
+                    goals += 1;
+                }
+                if tile == Tile::Player || tile == Tile::PlayerOnGoal {
+                    editor.player = Some((x, y));
+                }
+            }
+        }
+        editor.title = level.title.clone();
+        // the seed and solution don't hold for the edited board
+        editor.notes = level
+            .notes
+            .iter()
+            .filter(|n| {
+                let note = n.to_ascii_lowercase();
+                !note.starts_with("seed:") && !note.starts_with("solution:")
+            })
+            .cloned()
+            .collect();
+        editor
+    }
+
+    /// Opens level `index` of `path`, or a new level of `size` if the file
+    /// or the level doesn't exist yet. `save` writes it back there.
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        index: usize,
+        size: (usize, usize),
+    ) -> Result<Editor, LevelError> {
+        let levels = match load_collection(&path) {
+            Ok(levels) => levels,
+            Err(LevelError::Io(ref e)) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        let mut editor = match levels.get(index) {
+            Some(level) => Editor::from_level(level),
+            None => Editor::new(size),
+        };
+        editor.file = Some((path.as_ref().to_path_buf(), index.min(levels.len())));
+        Ok(editor)
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    pub fn cell(&self, x: usize, y: usize) -> &Cell {
+        &self.cells[y * self.width + x]
+    }
+
+    pub fn player(&self) -> Option<(usize, usize)> {
+        self.player
+    }
+
+    pub fn paint(&mut self, x: usize, y: usize, brush: &Brush) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let on_player = self.player == Some((x, y));
+        let cell = &mut self.cells[y * self.width + x];
+        match *brush {
+            Brush::Wall | Brush::Erase => {
+                *cell = Cell::new(if *brush == Brush::Wall {
+                    Ground::Wall
+                } else {
+                    Ground::Outside
+                });
+                if on_player {
+                    self.player = None;
+                }
+            }
+            Brush::Floor => *cell = Cell::new(Ground::Floor),
+            Brush::Crate(ref color) => {
+                cell.set_floor();
+                cell.crate_color = Some(color.clone());
+                if on_player {
+                    self.player = None;
+                }
+            }
+            Brush::Target(ref color) => {
+                cell.set_floor();
+                cell.goal_color = Some(color.clone());
+            }
+            Brush::Player => {
+                cell.set_floor();
+                cell.crate_color = None;
+                self.player = Some((x, y));
+            }
+            Brush::Tile(symbol, ref color) => {
+                *cell = Cell::new(Ground::Tile(symbol));
+                cell.tile_color = color.clone();
+                if on_player {
+                    self.player = None;
+                }
+            }
+        }
+    }
+
+    /// Changes the size of the board, keeping the top left corner. New
+    /// cells are blank space.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        let width = width.clamp(MIN_SIZE, MAX_SIZE);
+        let height = height.clamp(MIN_SIZE, MAX_SIZE);
+        let mut cells = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                cells.push(if x < self.width && y < self.height {
+                    self.cell(x, y).clone()
+                } else {
+                    Cell::new(Ground::Outside)
+                });
+            }
+        }
+        self.cells = cells;
+        self.width = width;
+        self.height = height;
+        if let Some((x, y)) = self.player {
+            if x >= width || y >= height {
+                self.player = None;
+            }
+        }
+    }
+
+    /// What keeps the board from being a playable level, nothing once it
+    /// is one. Whether it can be solved is up to the solver.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        // crates and targets of each color
+        let mut counts: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+        let mut loose = 0;
+        for (i, cell) in self.cells.iter().enumerate() {
+            if let Some(ref c) = cell.crate_color {
+                counts.entry(c).or_insert((0, 0)).0 += 1;
+            }
+            if let Some(ref g) = cell.goal_color {
+                counts.entry(g).or_insert((0, 0)).1 += 1;
+            }
+            match (&cell.crate_color, &cell.goal_color) {
+                (Some(c), Some(g)) if c != g => problems.push(format!(
+                    "the crate at ({}, {}) is on a target of another color",
+                    i % self.width,
+                    i / self.width
+                )),
+                (Some(_), None) => loose += 1,
+                _ => (),
+            }
+        }
+        for (color, &(crates, goals)) in counts.iter() {
+            if crates != goals {
+                problems.push(format!(
+                    "{} {} crates for {} targets",
+                    crates,
+                    color.trim_end_matches(".png"),
+                    goals
+                ));
+            }
+        }
+        if counts.is_empty() || loose == 0 {
+            problems.push("no crate to push".to_string());
+        }
+        match self.player {
+            None => problems.push("no player".to_string()),
+            Some(start) if !self.closed(start) => {
+                problems.push("the walls don't close the player in".to_string())
+            }
+            _ => (),
+        }
+        problems
+    }
+
+    /// Whether walking from `start` never leaves the board or reaches
+    /// blank space.
+    fn closed(&self, start: (usize, usize)) -> bool {
+        let mut seen = vec![false; self.cells.len()];
+        let mut stack = vec![start];
+        while let Some((x, y)) = stack.pop() {
+            let i = y * self.width + x;
+            if seen[i] || self.cells[i].ground == Ground::Wall {
+                continue;
+            }
+            if self.cells[i].ground == Ground::Outside
+                || x == 0
+                || y == 0
+                || x + 1 == self.width
+                || y + 1 == self.height
+            {
+                return false;
+            }
+            seen[i] = true;
+            stack.extend_from_slice(&[(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]);
+        }
+        true
+    }
+
+    /// The whole board being edited as a level, blank rows around the
+    /// walls included, for showing and play-testing it.
+    pub fn preview(&self) -> Level {
+        let mut tiles = Vec::with_capacity(self.height);
+        let mut crate_colors = Vec::new();
+        let mut goal_colors = Vec::new();
+        let mut paint_colors = Vec::new();
+        for y in 0..self.height {
+            let mut row = Vec::with_capacity(self.width);
+            for x in 0..self.width {
+                let cell = self.cell(x, y);
+                let player = self.player == Some((x, y));
+                row.push(match (cell.ground, &cell.crate_color, &cell.goal_color) {
+                    (Ground::Outside, _, _) => Tile::Outside,
+                    (Ground::Wall, _, _) => Tile::Wall,
+                    (Ground::Tile(symbol), _, _) => Tile::Special(symbol),
+                    (Ground::Floor, &Some(_), &Some(_)) => Tile::CrateOnGoal,
+                    (Ground::Floor, &Some(_), &None) => Tile::Crate,
+                    (Ground::Floor, &None, &Some(_)) if player => Tile::PlayerOnGoal,
+                    (Ground::Floor, &None, &Some(_)) => Tile::Goal,
+                    (Ground::Floor, &None, &None) if player => Tile::Player,
+                    (Ground::Floor, &None, &None) => Tile::Floor,
+                });
+                if let Some(ref c) = cell.crate_color {
+                    crate_colors.push(c.clone());
+                }
+                if let Some(ref g) = cell.goal_color {
+                    goal_colors.push(g.clone());
+                }
+                if let (Ground::Tile(_), Some(ref t)) = (cell.ground, &cell.tile_color) {
+                    paint_colors.push(t.clone());
+                }
+            }
+            tiles.push(row);
+        }
+        // plain XSB when every crate is the default one
+        if crate_colors
+            .iter()
+            .chain(goal_colors.iter())
+            .all(|c| c == DEFAULT_COLOR)
+        {
+            crate_colors.clear();
+            goal_colors.clear();
+        }
+        if paint_colors.iter().all(|c| c == DEFAULT_COLOR) {
+            paint_colors.clear();
+        }
+        Level {
+            title: self.title.clone(),
+            notes: self.notes.clone(),
+            tiles: tiles,
+            crate_colors: crate_colors,
+            goal_colors: goal_colors,
+            paint_colors: paint_colors,
+        }
+    }
+
+    /// The level for saving, without the rows of blank space above and
+    /// below the walls.
+    pub fn to_level(&self) -> Level {
+        let mut level = self.preview();
+        let used = |row: &Vec<Tile>| row.iter().any(|t| *t != Tile::Outside);
+        let first = level.tiles.iter().position(used).unwrap_or(0);
+        let last = level.tiles.iter().rposition(used).map_or(0, |i| i + 1);
+        level.tiles = level.tiles[first..last.max(first)].to_vec();
+        level
+    }
+
+    /// Writes the level to `file`, replacing the level at its index or
+    /// adding it after the others. Boards with `problems` aren't written.
+    pub fn save(&self) -> Result<PathBuf, SaveError> {
+        if let Some(problem) = self.problems().into_iter().next() {
+            return Err(SaveError::Unplayable(problem));
+        }
+        let (path, index) = match self.file {
+            Some((ref path, index)) => (path, index),
+            None => {
+                return Err(SaveError::Level(LevelError::Io(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "no file to save to",
+                ))))
+            }
+        };
+        let mut levels = match load_collection(path) {
+            Ok(levels) => levels,
+            Err(LevelError::Io(ref e)) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        if index < levels.len() {
+            levels[index] = self.to_level();
+        } else {
+            levels.push(self.to_level());
+        }
+        save_collection(path, &levels)?;
+        Ok(path.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn to_level_round_trips_through_from_level() {
+        let mut editor = Editor::new((6, 5));
+        assert!(editor.problems().contains(&"no player".to_string()));
+        editor.paint(1, 1, &Brush::Player);
+        editor.paint(2, 2, &Brush::Crate("red.png".to_string()));
+        editor.paint(3, 3, &Brush::Target("red.png".to_string()));
+        assert!(editor.problems().is_empty(), "{:?}", editor.problems());
+        let level = editor.to_level();
+        assert_eq!(
+            Editor::from_level(&level).to_level().to_xsb(),
+            level.to_xsb()
+        );
+    }
+
+    #[test]
+    fn paints_round_trip() {
+        let mut editor = Editor::new((6, 5));
+        editor.paint(1, 1, &Brush::Player);
+        editor.paint(2, 2, &Brush::Crate("red.png".to_string()));
+        editor.paint(3, 3, &Brush::Target("blue.png".to_string()));
+        editor.paint(4, 2, &Brush::Tile('%', Some("blue.png".to_string())));
+        let level = editor.to_level();
+        assert_eq!(level.paint_colors, vec!["blue.png".to_string()]);
+        assert_eq!(
+            Editor::from_level(&level).to_level().to_xsb(),
+            level.to_xsb()
+        );
+    }
+
+    #[test]
+    fn painting_over_a_paint_tile_drops_its_color() {
+        let mut editor = Editor::new((6, 5));
+        editor.paint(1, 1, &Brush::Player);
+        editor.paint(3, 3, &Brush::Target("blue.png".to_string()));
+        editor.paint(2, 2, &Brush::Tile('%', Some("blue.png".to_string())));
+        editor.paint(2, 2, &Brush::Crate("red.png".to_string()));
+        let level = editor.to_level();
+        assert!(level.paint_colors.is_empty());
+        assert!(Level::parse(&level.to_xsb()).is_ok());
+    }
+
+    #[test]
+    fn problems_name_open_walls() {
+        let mut editor = Editor::new((6, 5));
+        editor.paint(1, 1, &Brush::Player);
+        editor.paint(2, 2, &Brush::Crate("red.png".to_string()));
+        editor.paint(3, 3, &Brush::Target("red.png".to_string()));
+        editor.paint(0, 2, &Brush::Erase);
+        assert_eq!(
+            editor.problems(),
+            vec!["the walls don't close the player in".to_string()]
+        );
+    }
+
+    #[test]
+    fn grown_boards_save_without_blank_rows() {
+        let mut editor = Editor::new((6, 5));
+        editor.paint(1, 1, &Brush::Player);
+        editor.paint(2, 2, &Brush::Crate("red.png".to_string()));
+        editor.paint(3, 3, &Brush::Target("red.png".to_string()));
+        editor.notes.push("Author: me".to_string());
+        editor.resize(6, 8);
+        assert_eq!(editor.preview().height(), 8);
+        let level = Level::parse(&editor.to_level().to_xsb()).unwrap();
+        assert_eq!(level.height(), 5);
+        assert_eq!(level.meta("Author"), Some("me"));
+    }
+
+    #[test]
+    fn unplayable_boards_are_not_saved() {
+        let path = env::temp_dir().join("sokoban-editor-unplayable.txt");
+        let _ = fs::remove_file(&path);
+        let mut editor = Editor::open(&path, 0, (5, 5)).unwrap();
+        editor.paint(1, 1, &Brush::Player);
+        match editor.save() {
+            Err(SaveError::Unplayable(ref problem)) => assert_eq!(problem, "no crate to push"),
+            other => panic!("{:?}", other),
+        }
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn saved_levels_open_again() {
+        let path = env::temp_dir().join("sokoban-editor-save.txt");
+        let _ = fs::remove_file(&path);
+        let mut editor = Editor::open(&path, 0, (5, 5)).unwrap();
+        editor.paint(1, 1, &Brush::Player);
+        editor.paint(2, 2, &Brush::Crate("red.png".to_string()));
+        editor.paint(3, 3, &Brush::Target("red.png".to_string()));
+        editor.save().unwrap();
+        let editor = Editor::open(&path, 0, (5, 5)).unwrap();
+        assert_eq!(editor.player(), Some((1, 1)));
+    }
+}
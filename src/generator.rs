@@ -0,0 +1,497 @@
+//! Level generation by pulling crates away from their goals.
+//!
+//! Every crate starts on its goal and the player plays the level backwards,
+//! walking up to crates and pulling them along. Replaying those pulls in
+//! reverse as pushes solves the level, so every generated level can be
+//! solved. Crates are never pulled onto a goal, as in the game a crate
+//! landing on a matching goal is locked there and any other goal stops it.
+//!
+//! How hard a level is comes from where the walk left the crates, not from
+//! how long it was, so pulls that undo each other don't make a level look
+//! harder than it is.
+
+use std::collections::VecDeque;
+use std::ops::Range;
+
+use na::core::DMatrix;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use level::{Level, Tile, DEFAULT_COLOR};
+use state::{CollisionMatrix, Direction};
+
+const DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Down,
+    Direction::Left,
+    Direction::Right,
+];
+
+#[derive(Clone, Debug)]
+pub struct GeneratorConfig {
+    /// Fewest and most crates on a level.
+    pub crates: (usize, usize),
+    /// Pushes a level needs before it's accepted, see `Metrics`.
+    pub min_pushes: usize,
+    /// Box lines (runs of pushes of one crate in one direction) a level
+    /// needs before it's accepted.
+    pub min_box_lines: usize,
+    /// Pulls tried per attempt.
+    pub steps: usize,
+    /// Walks tried before settling for the best level found.
+    pub attempts: usize,
+    /// Share of the inside of the board turned into walls.
+    pub wall_density: f32,
+}
+
+/// How far the crates are from their goals: the pushes and box lines each
+/// needs to get back with the others out of the way, and the crates that
+/// have to move. Close to a lower bound of an optimal solution.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Metrics {
+    pub pushes: usize,
+    pub box_lines: usize,
+    pub box_changes: usize,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> GeneratorConfig {
+        GeneratorConfig {
+            crates: (3, 8),
+            min_pushes: 15,
+            min_box_lines: 6,
+            steps: 60,
+            attempts: 30,
+            wall_density: 0.12,
+        }
+    }
+}
+
+impl Metrics {
+    fn better_than(&self, other: &Metrics) -> bool {
+        (self.box_lines, self.pushes) > (other.box_lines, other.pushes)
+    }
+}
+
+/// Board layouts tried before giving up on reaching the wanted difficulty.
+const LAYOUTS: usize = 10;
+
+type Cell = (usize, usize);
+
+fn step(cell: Cell, dir: Direction, back: bool) -> Cell {
+    let offset = dir.offset();
+    let (dx, dy) = if back {
+        (-offset.get_x(), -offset.get_y())
+    } else {
+        (offset.get_x(), offset.get_y())
+    };
+    ((cell.0 as i32 + dx) as usize, (cell.1 as i32 + dy) as usize)
+}
+
+fn is_wall(walls: &CollisionMatrix, cell: Cell) -> bool {
+    walls.next((cell.1, cell.0), 0, 0)
+}
+
+/// A number in `range`, drawn as a u32 so a seed gives the same level
+/// whatever the size of usize.
+fn gen_index<R: Rng>(rng: &mut R, range: Range<usize>) -> usize {
+    rng.gen_range(range.start as u32..range.end as u32) as usize
+}
+
+/// Fewest pushes, or with `lines` fewest box lines, taking a crate from
+/// `from` to `to` with no other crate in the way. None if it can't get
+/// there.
+fn push_distance(walls: &CollisionMatrix, from: Cell, to: Cell, lines: bool) -> Option<usize> {
+    let mut dist =
+        DMatrix::<Option<usize>>::from_element(walls.coll.nrows(), walls.coll.ncols(), None);
+    dist[(from.1, from.0)] = Some(0);
+    let mut queue = VecDeque::new();
+    queue.push_back(from);
+    while let Some(cell) = queue.pop_front() {
+        let d = dist[(cell.1, cell.0)].unwrap();
+        if cell == to {
+            return Some(d);
+        }
+        for dir in DIRECTIONS.iter() {
+            let mut at = cell;
+            // a box line runs on as long as the crate can be pushed
+            loop {
+                let next = step(at, *dir, false);
+                if is_wall(walls, next) || is_wall(walls, step(at, *dir, true)) {
+                    break;
+                }
+                at = next;
+                if dist[(at.1, at.0)].is_none() {
+                    dist[(at.1, at.0)] = Some(d + 1);
+                    queue.push_back(at);
+                }
+                if !lines {
+                    break;
+                }
+            }
+        }
+    }
+    None
+}
+
+/// `Metrics` of `crates`, each taken to the nearest goal of its color.
+/// `goals` and `colors` are in the order of the crates they started with.
+fn metrics(walls: &CollisionMatrix, goals: &[Cell], colors: &[String], crates: &[Cell]) -> Metrics {
+    let mut metrics = Metrics::default();
+    for (i, from) in crates.iter().enumerate() {
+        let nearest = |lines: bool| {
+            goals
+                .iter()
+                .zip(colors.iter())
+                .filter(|&(_, color)| *color == colors[i])
+                .filter_map(|(to, _)| push_distance(walls, *from, *to, lines))
+                .min()
+                .unwrap_or(0)
+        };
+        let pushes = nearest(false);
+        if pushes > 0 {
+            metrics.pushes += pushes;
+            metrics.box_lines += nearest(true);
+            metrics.box_changes += 1;
+        }
+    }
+    metrics
+}
+
+fn floor_cells(walls: &CollisionMatrix) -> Vec<Cell> {
+    let mut cells = Vec::new();
+    for y in 0..walls.coll.nrows() {
+        for x in 0..walls.coll.ncols() {
+            if !walls.coll[(y, x)] {
+                cells.push((x, y));
+            }
+        }
+    }
+    cells
+}
+
+fn connected(walls: &CollisionMatrix) -> bool {
+    let cells = floor_cells(walls);
+    let first = match cells.first() {
+        Some(c) => *c,
+        None => return false,
+    };
+    let mut seen = DMatrix::<bool>::from_element(walls.coll.nrows(), walls.coll.ncols(), false);
+    let mut stack = vec![first];
+    let mut count = 0;
+    while let Some(cell) = stack.pop() {
+        if seen[(cell.1, cell.0)] || walls.coll[(cell.1, cell.0)] {
+            continue;
+        }
+        seen[(cell.1, cell.0)] = true;
+        count += 1;
+        for dir in DIRECTIONS.iter() {
+            stack.push(step(cell, *dir, false));
+        }
+    }
+    count == cells.len()
+}
+
+/// A walled rectangle with some walls scattered inside, keeping all of the
+/// floor reachable.
+fn gen_walls<R: Rng>(size: (u32, u32), density: f32, rng: &mut R) -> CollisionMatrix {
+    let (w, h) = (size.0 as usize, size.1 as usize);
+    let mut walls = CollisionMatrix {
+        coll: DMatrix::<bool>::from_element(h, w, false),
+    };
+    for x in 0..w {
+        for y in 0..h {
+            if x == 0 || y == 0 || x == w - 1 || y == h - 1 {
+                walls.coll[(y, x)] = true;
+            }
+        }
+    }
+    let wanted = ((w - 2) * (h - 2)) as f32 * density;
+    let mut placed = 0;
+    for _ in 0..(wanted as usize) * 10 {
+        if placed as f32 >= wanted {
+            break;
+        }
+        let (x, y) = (gen_index(rng, 1..w - 1), gen_index(rng, 1..h - 1));
+        if walls.coll[(y, x)] {
+            continue;
+        }
+        walls.coll[(y, x)] = true;
+        if connected(&walls) {
+            placed += 1;
+        } else {
+            walls.coll[(y, x)] = false;
+        }
+    }
+    walls
+}
+
+/// Board in the middle of a reverse walk.
+#[derive(Clone)]
+struct Walk {
+    crates: Vec<Cell>,
+    player: Cell,
+    metrics: Metrics,
+}
+
+impl Walk {
+    fn crate_at(&self, cell: Cell) -> Option<usize> {
+        self.crates.iter().position(|c| *c == cell)
+    }
+
+    fn any_on_goal(&self, goals: &[Cell]) -> bool {
+        self.crates.iter().any(|c| goals.contains(c))
+    }
+
+    /// Cells the player can walk to without moving a crate.
+    fn reachable(&self, walls: &CollisionMatrix) -> DMatrix<bool> {
+        let mut seen = DMatrix::<bool>::from_element(walls.coll.nrows(), walls.coll.ncols(), false);
+        let mut stack = vec![self.player];
+        while let Some(cell) = stack.pop() {
+            if seen[(cell.1, cell.0)] || is_wall(walls, cell) || self.crate_at(cell).is_some() {
+                continue;
+            }
+            seen[(cell.1, cell.0)] = true;
+            for dir in DIRECTIONS.iter() {
+                stack.push(step(cell, *dir, false));
+            }
+        }
+        seen
+    }
+
+    /// Walks to crate `i` and pulls it up to `times` cells towards `dir`,
+    /// returns whether it moved at all.
+    fn pull(
+        &mut self,
+        walls: &CollisionMatrix,
+        goals: &[Cell],
+        i: usize,
+        dir: Direction,
+        times: usize,
+    ) -> bool {
+        let free = |walk: &Walk, cell: Cell| !is_wall(walls, cell) && walk.crate_at(cell).is_none();
+        let start = step(self.crates[i], dir, false);
+        if !free(self, start) || !self.reachable(walls)[(start.1, start.0)] {
+            return false;
+        }
+        self.player = start;
+        let mut pulled = 0;
+        while pulled < times {
+            let to = step(self.player, dir, false);
+            if !free(self, to) || goals.contains(&self.player) {
+                break;
+            }
+            self.crates[i] = self.player;
+            self.player = to;
+            pulled += 1;
+        }
+        pulled > 0
+    }
+}
+
+/// Generates a solvable level of `size` with crates in `colors`, along
+/// with how hard it is to undo.
+pub fn generate<R: Rng>(
+    size: (u32, u32),
+    colors: &[String],
+    config: &GeneratorConfig,
+    rng: &mut R,
+) -> (Level, Metrics) {
+    let n_crates = gen_index(rng, config.crates.0..config.crates.1 + 1).max(1);
+    let crate_colors: Vec<String> = (0..n_crates)
+        .map(|_| {
+            colors
+                .choose(rng)
+                .cloned()
+                .unwrap_or_else(|| DEFAULT_COLOR.to_string())
+        })
+        .collect();
+
+    let mut best: Option<(CollisionMatrix, Vec<Cell>, Walk)> = None;
+    for _ in 0..LAYOUTS {
+        let walls = gen_walls(size, config.wall_density, rng);
+        let mut floor = floor_cells(&walls);
+        let n_crates = n_crates.min(floor.len() / 4).max(1);
+        let mut found: Option<(Vec<Cell>, Walk)> = None;
+        for _ in 0..config.attempts {
+            floor.shuffle(rng);
+            let goals: Vec<Cell> = floor[..n_crates].to_vec();
+            let player = floor[floor.len() - 1];
+            let walk = walk(&walls, &goals, &crate_colors, player, config, rng);
+            let walk = match walk {
+                Some(w) => w,
+                None => continue,
+            };
+            let improves = match found {
+                Some((_, ref b)) => walk.metrics.better_than(&b.metrics),
+                None => true,
+            };
+            if improves {
+                found = Some((goals, walk));
+            }
+            if let Some((_, ref b)) = found {
+                if b.metrics.pushes >= config.min_pushes
+                    && b.metrics.box_lines >= config.min_box_lines
+                {
+                    break;
+                }
+            }
+        }
+        if let Some((goals, walk)) = found {
+            let improves = match best {
+                Some((_, _, ref b)) => walk.metrics.better_than(&b.metrics),
+                None => true,
+            };
+            if improves {
+                best = Some((walls, goals, walk));
+            }
+        }
+        if let Some((_, _, ref b)) = best {
+            if b.metrics.pushes >= config.min_pushes && b.metrics.box_lines >= config.min_box_lines
+            {
+                break;
+            }
+        }
+    }
+    let (walls, goals, walk) = best.expect("board too small to place any crate");
+    (to_level(&walls, &goals, &crate_colors, &walk), walk.metrics)
+}
+
+/// Plays one level backwards from the solved position, returning the
+/// hardest position seen with every crate off its goal.
+fn walk<R: Rng>(
+    walls: &CollisionMatrix,
+    goals: &[Cell],
+    colors: &[String],
+    player: Cell,
+    config: &GeneratorConfig,
+    rng: &mut R,
+) -> Option<Walk> {
+    let n_crates = goals.len();
+    let mut walk = Walk {
+        crates: goals.to_vec(),
+        player: player,
+        metrics: Metrics::default(),
+    };
+    let mut best: Option<Walk> = None;
+    for _ in 0..config.steps {
+        // crates still on their goal are picked first
+        let i = match (0..n_crates).find(|i| goals.contains(&walk.crates[*i])) {
+            Some(i) if rng.gen_bool(0.5) => i,
+            _ => gen_index(rng, 0..n_crates),
+        };
+        let dir = *DIRECTIONS.choose(rng).unwrap();
+        let times = gen_index(rng, 1..5);
+        if !walk.pull(walls, goals, i, dir, times) {
+            continue;
+        }
+        walk.metrics = metrics(walls, goals, colors, &walk.crates);
+        let improves = match best {
+            Some(ref b) => walk.metrics.better_than(&b.metrics),
+            None => true,
+        };
+        if !walk.any_on_goal(goals) && improves {
+            best = Some(walk.clone());
+        }
+    }
+    best
+}
+
+fn to_level(walls: &CollisionMatrix, goals: &[Cell], colors: &[String], walk: &Walk) -> Level {
+    let (h, w) = (walls.coll.nrows(), walls.coll.ncols());
+    let mut tiles = vec![vec![Tile::Floor; w]; h];
+    let mut crate_colors = Vec::new();
+    let mut goal_colors = Vec::new();
+    for (y, row) in tiles.iter_mut().enumerate() {
+        for (x, tile) in row.iter_mut().enumerate() {
+            let cell = (x, y);
+            let goal = goals.iter().position(|g| *g == cell);
+            let crate_ = walk.crate_at(cell);
+            *tile = if walls.coll[(y, x)] {
+                Tile::Wall
+            } else if crate_.is_some() && goal.is_some() {
+                Tile::CrateOnGoal
+            } else if crate_.is_some() {
+                Tile::Crate
+            } else if goal.is_some() && walk.player == cell {
+                Tile::PlayerOnGoal
+            } else if goal.is_some() {
+                Tile::Goal
+            } else if walk.player == cell {
+                Tile::Player
+            } else {
+                Tile::Floor
+            };
+            if let Some(i) = crate_ {
+                crate_colors.push(colors[i].clone());
+            }
+            if let Some(i) = goal {
+                goal_colors.push(colors[i].clone());
+            }
+        }
+    }
+    Level {
+        title: None,
+        notes: Vec::new(),
+        tiles: tiles,
+        crate_colors: crate_colors,
+        goal_colors: goal_colors,
+        paint_colors: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{thread_rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+    use solver::solve;
+    use state::GameState;
+
+    #[test]
+    fn generated_levels_parse_back_with_a_crate_per_goal() {
+        let colors = vec!["red.png".to_string(), "blue.png".to_string()];
+        let config = GeneratorConfig::default();
+        let mut rng = thread_rng();
+        for _ in 0..20 {
+            let (level, metrics) = generate((12, 9), &colors, &config, &mut rng);
+            let again = Level::parse(&level.to_xsb()).unwrap();
+            let count = |f: fn(&Tile) -> bool| {
+                again
+                    .tiles
+                    .iter()
+                    .flat_map(|r| r.iter())
+                    .filter(|t| f(t))
+                    .count()
+            };
+            assert_eq!(count(Tile::is_crate), count(Tile::is_goal));
+            assert!(metrics.pushes > 0);
+        }
+    }
+
+    #[test]
+    fn metrics_never_ask_for_more_pushes_than_a_solution() {
+        let config = GeneratorConfig {
+            crates: (1, 2),
+            ..GeneratorConfig::default()
+        };
+        let mut rng = thread_rng();
+        for _ in 0..3 {
+            let (level, metrics) = generate((8, 7), &[], &config, &mut rng);
+            let mut state = GameState::new((8, 7), Vec::new());
+            state.load_level(&level);
+            if let Ok(solution) = solve(&state, 100_000) {
+                assert!(solution.pushes.len() >= metrics.pushes);
+            }
+        }
+    }
+
+    #[test]
+    fn indices_are_drawn_as_u32() {
+        let mut a = ChaCha8Rng::seed_from_u64(5);
+        let mut b = ChaCha8Rng::seed_from_u64(5);
+        for _ in 0..100 {
+            assert_eq!(gen_index(&mut a, 1..13), b.gen_range(1u32..13) as usize);
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use state::{Object, Player, Score};
+
+/// The parts of a `GameState` a move can change.
+#[derive(Clone)]
+pub struct Snapshot {
+    /// Holes filled by crates turn to floor.
+    pub floor: Vec<Object>,
+    pub special: Vec<Object>,
+    pub player: Player,
+    pub score: Score,
+    pub targets_left: i32,
+    /// Length of the LURD record.
+    pub lurd_len: usize,
+}
+
+/// Unlimited undo / redo of moves. Whole snapshots are kept, so locked
+/// crates and the targets they consumed come back as they were.
+pub struct History {
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+}
+
+impl History {
+    pub fn new() -> History {
+        History {
+            undo: Vec::new(),
+            redo: Vec::new(),
+        }
+    }
+
+    /// Records the state before a move, dropping anything that could be
+    /// redone.
+    pub fn record(&mut self, before: Snapshot) {
+        self.undo.push(before);
+        self.redo.clear();
+    }
+
+    pub fn undo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let prev = self.undo.pop()?;
+        self.redo.push(current);
+        Some(prev)
+    }
+
+    pub fn redo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let next = self.redo.pop()?;
+        self.undo.push(current);
+        Some(next)
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+impl Default for History {
+    fn default() -> History {
+        History::new()
+    }
+}
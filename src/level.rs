@@ -0,0 +1,559 @@
+//! Levels in the XSB text format.
+//!
+//! Crate colors are stored as an extension on top of standard XSB, two
+//! metadata lines listing the color of every crate and every goal in
+//! reading order (left to right, top to bottom):
+//!
+//! ```text
+//! #######
+//! #@$ . #
+//! # $ *.#
+//! #######
+//! Crates: red.png blue.png green.png
+//! Goals: blue.png green.png red.png
+//! ```
+//!
+//! A `*` takes one entry from each list and a `+` one from `Goals`. Colors
+//! are the keys of the crate textures, so the file names under
+//! `assets/crates`. Levels without the lines use `DEFAULT_COLOR`, and other
+//! programs simply see them as metadata.
+//!
+//! Special tiles such as ice are written with their own symbols, see
+//! `tiles`. Those that come in colors, paint tiles, take theirs from a
+//! `Paints:` line the same way.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use tiles;
+
+/// Color given to crates and goals of levels that don't specify one,
+/// the plain wooden crate.
+pub const DEFAULT_COLOR: &str = "brown.png";
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Tile {
+    Outside,
+    Wall,
+    Floor,
+    Goal,
+    Crate,
+    CrateOnGoal,
+    Player,
+    PlayerOnGoal,
+    /// A special tile, by its symbol.
+    Special(char),
+}
+
+/// A single puzzle in the XSB text format.
+///
+/// `notes` keeps every non-board line that belongs to the level (`;`
+/// comments, `Author: ...` and similar metadata) verbatim, except the
+/// title and the crate colors which have their own fields.
+#[derive(Clone, Debug)]
+pub struct Level {
+    pub title: Option<String>,
+    pub notes: Vec<String>,
+    pub tiles: Vec<Vec<Tile>>,
+    /// Colors of the crates in reading order, empty for plain XSB.
+    pub crate_colors: Vec<String>,
+    /// Colors of the goals in reading order, empty for plain XSB.
+    pub goal_colors: Vec<String>,
+    /// Colors of the special tiles that come in colors, in reading order.
+    pub paint_colors: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum LevelError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LevelError::Io(ref e) => write!(f, "{}", e),
+            LevelError::Parse { line, ref message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl Error for LevelError {}
+
+impl From<io::Error> for LevelError {
+    fn from(e: io::Error) -> LevelError {
+        LevelError::Io(e)
+    }
+}
+
+impl Tile {
+    fn from_char(c: char) -> Option<Tile> {
+        match c {
+            '#' => Some(Tile::Wall),
+            ' ' | '-' | '_' => Some(Tile::Floor),
+            '.' => Some(Tile::Goal),
+            '$' => Some(Tile::Crate),
+            '*' => Some(Tile::CrateOnGoal),
+            '@' => Some(Tile::Player),
+            '+' => Some(Tile::PlayerOnGoal),
+            c if tiles::by_symbol(c).is_some() => Some(Tile::Special(c)),
+            _ => None,
+        }
+    }
+
+    pub fn to_char(&self) -> char {
+        match *self {
+            Tile::Outside | Tile::Floor => ' ',
+            Tile::Wall => '#',
+            Tile::Goal => '.',
+            Tile::Crate => '$',
+            Tile::CrateOnGoal => '*',
+            Tile::Player => '@',
+            Tile::PlayerOnGoal => '+',
+            Tile::Special(c) => c,
+        }
+    }
+
+    pub fn is_goal(&self) -> bool {
+        matches!(*self, Tile::Goal | Tile::CrateOnGoal | Tile::PlayerOnGoal)
+    }
+
+    pub fn is_crate(&self) -> bool {
+        matches!(*self, Tile::Crate | Tile::CrateOnGoal)
+    }
+}
+
+/// Whether `line` is a board row, tile characters starting and ending
+/// with a wall. Special tiles use letters and `:`, so notes such as
+/// `To: #` could otherwise pass for one.
+fn is_board_line(line: &str) -> bool {
+    let blank = |c: char| c == ' ' || c == '-' || c == '_';
+    let row = line.trim_matches(blank);
+    let meta = split_meta(line).is_some_and(|(key, _)| !key.contains('#'));
+    row.starts_with('#')
+        && row.ends_with('#')
+        && !meta
+        && line.chars().all(|c| Tile::from_char(c).is_some())
+}
+
+impl Level {
+    pub fn width(&self) -> usize {
+        self.tiles.iter().map(|r| r.len()).max().unwrap_or(0)
+    }
+
+    pub fn height(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Tile {
+        self.tiles
+            .get(y)
+            .and_then(|r| r.get(x))
+            .cloned()
+            .unwrap_or(Tile::Outside)
+    }
+
+    /// Color of the `n`th crate in reading order.
+    pub fn crate_color(&self, n: usize) -> &str {
+        self.crate_colors
+            .get(n)
+            .map(|c| c.as_str())
+            .unwrap_or(DEFAULT_COLOR)
+    }
+
+    /// Color of the `n`th goal in reading order.
+    pub fn goal_color(&self, n: usize) -> &str {
+        self.goal_colors
+            .get(n)
+            .map(|c| c.as_str())
+            .unwrap_or(DEFAULT_COLOR)
+    }
+
+    /// Color of the `n`th colored special tile in reading order.
+    pub fn paint_color(&self, n: usize) -> &str {
+        self.paint_colors
+            .get(n)
+            .map(|c| c.as_str())
+            .unwrap_or(DEFAULT_COLOR)
+    }
+
+    /// The level with every crate, goal and paint in `DEFAULT_COLOR`, so
+    /// any crate fits any goal.
+    pub fn monochrome(&self) -> Level {
+        let mut level = self.clone();
+        level.crate_colors.clear();
+        level.goal_colors.clear();
+        level.paint_colors.clear();
+        level
+    }
+
+    /// Value of a `Key: value` note, if the level has one.
+    pub fn meta(&self, key: &str) -> Option<&str> {
+        self.notes
+            .iter()
+            .filter_map(|n| split_meta(n))
+            .find(|m| m.0.eq_ignore_ascii_case(key))
+            .map(|m| m.1)
+    }
+
+    /// Parses a text holding exactly one level.
+    pub fn parse(text: &str) -> Result<Level, LevelError> {
+        let mut levels = parse_collection(text)?;
+        if levels.len() != 1 {
+            return Err(LevelError::Parse {
+                line: 0,
+                message: format!("expected one level, found {}", levels.len()),
+            });
+        }
+        Ok(levels.remove(0))
+    }
+
+    /// Builds a level out of board rows, telling the floor inside the walls
+    /// apart from the blank space around them by flooding from the player.
+    fn from_rows(rows: &[String], first_line: usize) -> Result<Level, LevelError> {
+        let mut tiles: Vec<Vec<Tile>> = rows
+            .iter()
+            .map(|r| r.chars().map(|c| Tile::from_char(c).unwrap()).collect())
+            .collect();
+
+        let mut player = None;
+        for (y, row) in tiles.iter().enumerate() {
+            for (x, t) in row.iter().enumerate() {
+                if *t == Tile::Player || *t == Tile::PlayerOnGoal {
+                    if player.is_some() {
+                        return Err(LevelError::Parse {
+                            line: first_line + y,
+                            message: "more than one player".to_string(),
+                        });
+                    }
+                    player = Some((x, y));
+                }
+            }
+        }
+        let player = match player {
+            Some(p) => p,
+            None => {
+                return Err(LevelError::Parse {
+                    line: first_line,
+                    message: "level has no player".to_string(),
+                })
+            }
+        };
+
+        let mut inside: Vec<Vec<bool>> = tiles.iter().map(|r| vec![false; r.len()]).collect();
+        let mut stack = vec![player];
+        while let Some((x, y)) = stack.pop() {
+            if y >= tiles.len() || x >= tiles[y].len() || inside[y][x] || tiles[y][x] == Tile::Wall
+            {
+                continue;
+            }
+            inside[y][x] = true;
+            stack.push((x + 1, y));
+            stack.push((x, y + 1));
+            if x > 0 {
+                stack.push((x - 1, y));
+            }
+            if y > 0 {
+                stack.push((x, y - 1));
+            }
+        }
+        let width = tiles.iter().map(|r| r.len()).max().unwrap_or(0);
+        for (y, row) in tiles.iter_mut().enumerate() {
+            for (x, t) in row.iter_mut().enumerate() {
+                if *t == Tile::Floor && !inside[y][x] {
+                    *t = Tile::Outside;
+                }
+            }
+            row.resize(width, Tile::Outside);
+        }
+
+        Ok(Level {
+            title: None,
+            notes: Vec::new(),
+            tiles: tiles,
+            crate_colors: Vec::new(),
+            goal_colors: Vec::new(),
+            paint_colors: Vec::new(),
+        })
+    }
+
+    /// Moves the `Crates:`, `Goals:` and `Paints:` notes into their fields,
+    /// checking they name one color per crate, goal and paint.
+    fn take_colors(&mut self, first_line: usize) -> Result<(), LevelError> {
+        let mut notes = Vec::new();
+        for note in self.notes.drain(..) {
+            let colors = match split_meta(&note) {
+                Some((key, value)) => {
+                    let colors: Vec<String> = value.split_whitespace().map(String::from).collect();
+                    Some((key.to_ascii_lowercase(), colors))
+                }
+                None => None,
+            };
+            match colors {
+                Some((ref key, ref c)) if key == "crates" => self.crate_colors = c.clone(),
+                Some((ref key, ref c)) if key == "goals" => self.goal_colors = c.clone(),
+                Some((ref key, ref c)) if key == "paints" => self.paint_colors = c.clone(),
+                _ => notes.push(note),
+            }
+        }
+        self.notes = notes;
+
+        let tiles = self.tiles.iter().flat_map(|r| r.iter());
+        let crates = tiles.clone().filter(|t| t.is_crate()).count();
+        let goals = tiles.clone().filter(|t| t.is_goal()).count();
+        let paints = tiles
+            .filter(|t| match **t {
+                Tile::Special(symbol) => tiles::by_symbol(symbol).is_some_and(|b| b.colored()),
+                _ => false,
+            })
+            .count();
+        if !self.crate_colors.is_empty() && self.crate_colors.len() != crates {
+            return Err(LevelError::Parse {
+                line: first_line,
+                message: format!(
+                    "{} crate colors for {} crates",
+                    self.crate_colors.len(),
+                    crates
+                ),
+            });
+        }
+        if !self.goal_colors.is_empty() && self.goal_colors.len() != goals {
+            return Err(LevelError::Parse {
+                line: first_line,
+                message: format!("{} goal colors for {} goals", self.goal_colors.len(), goals),
+            });
+        }
+        if !self.paint_colors.is_empty() && self.paint_colors.len() != paints {
+            return Err(LevelError::Parse {
+                line: first_line,
+                message: format!(
+                    "{} paint colors for {} paints",
+                    self.paint_colors.len(),
+                    paints
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn to_xsb(&self) -> String {
+        let mut out = String::new();
+        for row in self.tiles.iter() {
+            let line: String = row.iter().map(|t| t.to_char()).collect();
+            // a blank line would end the board
+            if line.trim().is_empty() {
+                continue;
+            }
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+        if let Some(ref title) = self.title {
+            out.push_str(&format!("Title: {}\n", title));
+        }
+        if !self.crate_colors.is_empty() {
+            out.push_str(&format!("Crates: {}\n", self.crate_colors.join(" ")));
+        }
+        if !self.goal_colors.is_empty() {
+            out.push_str(&format!("Goals: {}\n", self.goal_colors.join(" ")));
+        }
+        if !self.paint_colors.is_empty() {
+            out.push_str(&format!("Paints: {}\n", self.paint_colors.join(" ")));
+        }
+        for note in self.notes.iter() {
+            out.push_str(note);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_xsb())
+    }
+}
+
+fn split_meta(line: &str) -> Option<(&str, &str)> {
+    let idx = line.find(':')?;
+    let key = line[..idx].trim();
+    if key.is_empty() || key.starts_with(';') || key.contains(' ') {
+        return None;
+    }
+    Some((key, line[idx + 1..].trim()))
+}
+
+/// Parses a collection (`.sok`, `.txt`, `.xsb`) of levels.
+///
+/// Text right after a board, up to the next blank line, belongs to that
+/// board (`Title: ...`, `Author: ...`, comments). Any other text belongs to
+/// the board that follows it, where a plain line just above the board is
+/// taken as its title.
+pub fn parse_collection(text: &str) -> Result<Vec<Level>, LevelError> {
+    let mut levels = Vec::new();
+    let mut starts = Vec::new();
+    let mut pending: Vec<String> = Vec::new();
+    let mut rows: Vec<String> = Vec::new();
+    let mut first_line = 0;
+    // true while reading the lines that trail the last board
+    let mut trailing = false;
+
+    for (i, raw) in text.lines().enumerate() {
+        let line = raw.trim_end();
+        if is_board_line(line) {
+            if rows.is_empty() {
+                first_line = i + 1;
+            }
+            rows.push(line.to_string());
+            continue;
+        }
+        if !rows.is_empty() {
+            levels.push(finish_level(&rows, first_line, &mut pending)?);
+            starts.push(first_line);
+            rows.clear();
+            trailing = true;
+        }
+        if line.trim().is_empty() {
+            trailing = false;
+            continue;
+        }
+        if trailing {
+            let level = levels.last_mut().unwrap();
+            match split_meta(line) {
+                Some((key, value)) if key.eq_ignore_ascii_case("title") => {
+                    level.title = Some(value.to_string())
+                }
+                _ => level.notes.push(line.to_string()),
+            }
+        } else {
+            pending.push(line.to_string());
+        }
+    }
+    if !rows.is_empty() {
+        levels.push(finish_level(&rows, first_line, &mut pending)?);
+        starts.push(first_line);
+    }
+    for (level, line) in levels.iter_mut().zip(starts) {
+        level.take_colors(line)?;
+    }
+    Ok(levels)
+}
+
+fn finish_level(
+    rows: &[String],
+    first_line: usize,
+    pending: &mut Vec<String>,
+) -> Result<Level, LevelError> {
+    let mut level = Level::from_rows(rows, first_line)?;
+    if let Some(last) = pending.last() {
+        if !last.starts_with(';') && split_meta(last).is_none() {
+            level.title = Some(last.trim().to_string());
+        }
+    }
+    if level.title.is_some() {
+        pending.pop();
+    }
+    level.notes.append(pending);
+    Ok(level)
+}
+
+pub fn write_collection(levels: &[Level]) -> String {
+    levels
+        .iter()
+        .map(|l| l.to_xsb())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn load_collection<P: AsRef<Path>>(path: P) -> Result<Vec<Level>, LevelError> {
+    let text = fs::read_to_string(path)?;
+    parse_collection(&text)
+}
+
+pub fn save_collection<P: AsRef<Path>>(path: P, levels: &[Level]) -> Result<(), LevelError> {
+    fs::write(path, write_collection(levels))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_levels_round_trip() {
+        let text = "#####\n#@$.#\n#####\nTitle: Tiny\nAuthor: someone\n";
+        let level = Level::parse(text).unwrap();
+        assert_eq!(level.title.as_deref(), Some("Tiny"));
+        assert_eq!(level.notes, vec!["Author: someone".to_string()]);
+        assert_eq!(level.height(), 3);
+        assert_eq!(level.get(2, 1), Tile::Crate);
+        assert_eq!(
+            Level::parse(&level.to_xsb()).unwrap().to_xsb(),
+            level.to_xsb()
+        );
+    }
+
+    #[test]
+    fn collections_split_on_blank_lines() {
+        let text = "; 1\n#####\n#@$.#\n#####\n\nSecond\n######\n#@ $.#\n######\n";
+        let levels = parse_collection(text).unwrap();
+        assert_eq!(levels.len(), 2);
+        assert_eq!(levels[0].notes, vec!["; 1".to_string()]);
+        assert_eq!(levels[1].title.as_deref(), Some("Second"));
+        assert_eq!(levels[1].width(), 6);
+        let written = write_collection(&levels);
+        assert_eq!(
+            write_collection(&parse_collection(&written).unwrap()),
+            written
+        );
+    }
+
+    #[test]
+    fn colors_round_trip() {
+        let text =
+            "########\n#@$ .$.#\n########\nCrates: red.png blue.png\nGoals: blue.png red.png\n";
+        let level = Level::parse(text).unwrap();
+        assert_eq!(level.crate_color(1), "blue.png");
+        assert_eq!(level.goal_color(0), "blue.png");
+        assert_eq!(level.to_xsb(), text);
+        let plain = Level::parse("#####\n#@$.#\n#####\n").unwrap();
+        assert_eq!(plain.crate_color(0), DEFAULT_COLOR);
+    }
+
+    #[test]
+    fn color_counts_must_match_the_board() {
+        assert!(Level::parse("#####\n#@$.#\n#####\nCrates: red.png blue.png\n").is_err());
+        assert!(Level::parse("#####\n#@$.#\n#####\nGoals: red.png red.png\n").is_err());
+    }
+
+    #[test]
+    fn special_tiles_round_trip() {
+        let text = "#########\n#@$~o<T.#\n#  =:T  #\n#########\n";
+        let level = Level::parse(text).unwrap();
+        assert_eq!(level.to_xsb(), text);
+    }
+
+    #[test]
+    fn paints_round_trip() {
+        let text = "#######\n#@$%%.#\n#######\nPaints: red.png blue.png\n";
+        let level = Level::parse(text).unwrap();
+        assert_eq!(level.paint_color(1), "blue.png");
+        assert_eq!(level.to_xsb(), text);
+        assert!(Level::parse("######\n#@$%.#\n######\nPaints: red.png red.png\n").is_err());
+    }
+
+    #[test]
+    fn notes_made_of_tile_characters_are_not_rows() {
+        let level = Level::parse("#####\n#@$.#\n#####\nTo: #\n").unwrap();
+        assert_eq!(level.height(), 3);
+        assert_eq!(level.meta("To"), Some("#"));
+    }
+
+    #[test]
+    fn levels_need_one_player() {
+        assert!(Level::parse("#####\n# $.#\n#####\n").is_err());
+        assert!(Level::parse("#####\n#@$@#\n#####\n").is_err());
+    }
+}
@@ -0,0 +1,19 @@
+extern crate nalgebra as na;
+extern crate rand;
+extern crate rand_chacha;
+
+pub mod cli;
+pub mod commands;
+pub mod deadlock;
+pub mod editor;
+pub mod generator;
+pub mod history;
+pub mod level;
+pub mod pack;
+pub mod path;
+pub mod replay;
+pub mod scoring;
+pub mod solver;
+pub mod state;
+pub mod structs;
+pub mod tiles;
@@ -1,81 +1,109 @@
-extern crate nalgebra as na;
 extern crate opengl_graphics;
 extern crate piston_window;
-extern crate rand;
 extern crate sdl2_window;
+extern crate sokoban;
+
+mod animation;
+mod camera;
 
-use std::cell::RefCell;
 use std::collections::HashMap;
-use std::sync::mpsc;
 use std::sync::Arc;
 use std::sync::RwLock;
-use std::thread;
 
-use na::core::DMatrix;
+use animation::Animation;
+use camera::Camera;
 use opengl_graphics::GlGraphics;
 use opengl_graphics::{GlyphCache, Texture as Tex};
 use piston_window::{
-    clear, image, text, AdvancedWindow, Button, Key, OpenGL, PistonWindow, PressEvent,
-    ReleaseEvent, RenderArgs, RenderEvent, TextureSettings, Transformed, UpdateArgs, UpdateEvent,
-    WindowSettings,
+    clear, image, rectangle, text, AdvancedWindow, Button, Image, Key, MouseButton,
+    MouseCursorEvent, MouseScrollEvent, OpenGL, PistonWindow, PressEvent, ReleaseEvent, RenderArgs,
+    RenderEvent, TextureSettings, Transformed, UpdateArgs, UpdateEvent, WindowSettings,
 };
-use rand::prelude::IteratorRandom;
-use rand::Rng;
 use sdl2_window::Sdl2Window;
-use std::borrow::Borrow;
+use sokoban::cli::{self, Command};
+use sokoban::commands;
+use sokoban::editor::{Brush, Editor, SaveError};
+use sokoban::level::{save_collection, DEFAULT_COLOR};
+use sokoban::pack::{data_dir, Progress, Session};
+use sokoban::path;
+use sokoban::replay::{Replay, Verdict};
+use sokoban::solver::{solve, SolveError};
+use sokoban::state::*;
+use sokoban::structs::Position;
+use sokoban::tiles;
+use std::env;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
 use std::time::*;
-use structs::*;
 
-pub mod structs;
-#[derive(PartialEq, Copy, Clone, Debug)]
-enum ObjectType {
-    Blocking,
-    Passing,
-    Crate,
-    Target,
-}
-#[derive(PartialEq)]
-enum Direction {
-    Up,
-    Down,
-    Left,
-    Right,
-}
-struct Player {
-    sprite: Option<Arc<RwLock<Tex>>>,
-    position: Position,
-    canMove: bool,
-}
-struct Object {
-    sprite: Option<(String, Arc<RwLock<Tex>>)>,
-    obj_type: ObjectType,
-    position: Position,
-}
-struct CollisionMatrix {
-    coll: DMatrix<bool>,
-}
-struct Score {
-    moves: i32,
-    scored: i32,
+/// Side of a tile texture in pixels.
+const TILE: u32 = 64;
+/// Height of the score bar under the board.
+const HUD_HEIGHT: u32 = 58;
+/// Seconds between two moves while the solution plays itself.
+const AUTOPLAY_STEP: f64 = 0.15;
+const EDITOR_HELP: &str = "1 wall 2 floor 3 crate 4 target 5 player 6 erase 7 tiles  \
+                           C color  arrows resize  V check  T test  E play  Ctrl+S save";
+
+/// What the keys and the mouse do.
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Play,
+    /// Building the level in `Game::editor`.
+    Edit,
+    /// Playing the level being built, back to `Edit` once solved.
+    Test,
 }
+
 struct Game {
-    gl: Option<GlGraphics>,
-    floor: Vec<Object>,
-    special: Vec<Object>,
-    player: Player,
-    obj_tex: Option<GameTextures>,
-    player_tex: Option<PlayerTextures>,
-    start_t: SystemTime,
-    score: Score,
-    targets_left: i32,
-    size: (u32, u32),
+    gl: GlGraphics,
+    state: GameState,
+    obj_tex: GameTextures,
+    player_tex: PlayerTextures,
+    camera: Camera,
+    animation: Animation,
+    /// Size of the board area of the window, as last drawn.
+    view: [f64; 2],
+    cursor: [f64; 2],
+    /// Tile the left mouse button went down on.
+    drag: Option<Position>,
+    session: Session,
+    ctrl: bool,
+    hint: Option<String>,
+    /// Moves left to auto-play, the next one last.
+    autoplay: Vec<Direction>,
+    autoplay_wait: f64,
+    /// Seconds between two auto-played moves.
+    autoplay_step: f64,
+    paused: bool,
+    mode: Mode,
+    editor: Option<Editor>,
+    brush: Brush,
+    /// Brush painted with while a mouse button is held in the editor.
+    painting: Option<Brush>,
+    /// Positions the solver may look at before a hint gives up.
+    solver_limit: usize,
+    assets: PathBuf,
 }
+/// One picture of the walk cycle.
+#[derive(Clone)]
+struct Frame {
+    tex: Arc<RwLock<Tex>>,
+    /// Drawn flipped left to right.
+    mirrored: bool,
+    /// Share of the height taken off, keeping the feet on the ground.
+    squat: f64,
+}
+/// Walk cycle facing each way, `player_n.png` then `player_n_1.png`,
+/// `player_n_2.png` and so on if there are more frames. A single
+/// sprite gets a second frame made from it, mirrored when walking up
+/// or down and squatting a little when walking sideways.
 struct PlayerTextures {
-    player_n: Arc<RwLock<Tex>>,
-    player_s: Arc<RwLock<Tex>>,
-    player_e: Arc<RwLock<Tex>>,
-    player_w: Arc<RwLock<Tex>>,
+    player_n: Vec<Frame>,
+    player_s: Vec<Frame>,
+    player_e: Vec<Frame>,
+    player_w: Vec<Frame>,
 }
 struct GameTextures {
     wall: Arc<RwLock<Tex>>,
@@ -83,575 +111,920 @@ struct GameTextures {
     _crate: HashMap<String, Arc<RwLock<Tex>>>,
     b_crate: HashMap<String, Arc<RwLock<Tex>>>,
     targets: HashMap<String, Arc<RwLock<Tex>>>,
+    /// Special tiles by sprite name.
+    tiles: HashMap<String, Arc<RwLock<Tex>>>,
 }
-impl Object {
-    fn new(
-        position: Position,
-        obj_type: ObjectType,
-        sprite: Option<(String, Arc<RwLock<Tex>>)>,
-    ) -> Object {
-        Object {
-            position: position,
-            obj_type: obj_type,
-            sprite: sprite,
+
+impl PlayerTextures {
+    fn facing(&self, dir: Direction) -> &[Frame] {
+        match dir {
+            Direction::Up => &self.player_n,
+            Direction::Down => &self.player_s,
+            Direction::Left => &self.player_w,
+            Direction::Right => &self.player_e,
         }
     }
 }
 
-impl CollisionMatrix {
-    pub fn check_surroundings(&self, xy: (usize, usize), times: i32) -> bool {
-        let mut is_invalid = false;
-
-        for i in -times..times + 1 {
-            for j in -times..times + 1 {
-                is_invalid = is_invalid || self.next(xy, i, j);
-                //println!("next of {:?} in {:?} is {}",
-                //         xy,
-                //         (i, j),
-                //         self.next(xy, i, j));
-            }
-        }
-        is_invalid
-    }
-    pub fn next(&self, ind: (usize, usize), x: i32, y: i32) -> bool {
-        if ind.0 as i32 + y < 0
-            || ind.1 as i32 + x < 0
-            || ind.0 as i32 + y > 9
-            || ind.1 as i32 + x > 14
-        {
-            false
-        } else {
-            self.coll[((ind.0 as i32 + y) as usize, (ind.1 as i32 + x) as usize)]
+impl GameTextures {
+    /// Texture for an object's sprite name. Crates `placed` on their
+    /// target use the `blocked` variant of their color.
+    fn sprite(&self, obj: &Object, placed: bool) -> Option<&Arc<RwLock<Tex>>> {
+        let name = obj.sprite.as_ref()?;
+        match obj.obj_type {
+            ObjectType::Crate if placed => self.b_crate.get(name),
+            ObjectType::Crate => self._crate.get(name),
+            ObjectType::Target => self.targets.get(name),
+            ObjectType::Passing if name != "floor" => self.tiles.get(name),
+            _ => match name.as_str() {
+                "wall" => Some(&self.wall),
+                "floor" => Some(&self.floor),
+                _ => self.b_crate.get(name),
+            },
         }
     }
 }
-impl Score {
-    pub fn new() -> Score {
-        Score {
-            moves: 0,
-            scored: 0,
-        }
+
+/// Window size fitting a board of `dimensions` tiles of `tile` pixels,
+/// plus the score bar.
+fn window_size(dimensions: (usize, usize), tile: u32) -> (u32, u32) {
+    (
+        dimensions.0 as u32 * tile,
+        dimensions.1 as u32 * tile + HUD_HEIGHT,
+    )
+}
+
+fn direction(key: Key) -> Option<Direction> {
+    match key {
+        Key::Up => Some(Direction::Up),
+        Key::Down => Some(Direction::Down),
+        Key::Left => Some(Direction::Left),
+        Key::Right => Some(Direction::Right),
+        _ => None,
     }
 }
 
 impl Game {
-    fn new(size: (usize, usize)) -> Game {
-        let score = Score::new();
-        let start = SystemTime::now();
-        let mut c_matrix = CollisionMatrix {
-            coll: DMatrix::<bool>::from_element(size.0, size.1, false),
+    fn new(state: GameState, assets: &Path, solver_limit: usize) -> Game {
+        let load = |path: PathBuf| {
+            Arc::new(RwLock::new(
+                Tex::from_path(&path, &TextureSettings::new())
+                    .unwrap_or_else(|e| panic!("could not load {}: {}", path.display(), e)),
+            ))
         };
-        let player_tex = PlayerTextures {
-            player_n: Arc::new(RwLock::new(
-                Tex::from_path("./assets/player_n.png", &TextureSettings::new()).unwrap(),
-            )),
-            player_s: Arc::new(RwLock::new(
-                Tex::from_path("./assets/player_s.png", &TextureSettings::new()).unwrap(),
-            )),
-            player_e: Arc::new(RwLock::new(
-                Tex::from_path("./assets/player_e.png", &TextureSettings::new()).unwrap(),
-            )),
-            player_w: Arc::new(RwLock::new(
-                Tex::from_path("./assets/player_w.png", &TextureSettings::new()).unwrap(),
-            )),
-        };
-
         let mut crate_tex = Vec::new();
-        for path in fs::read_dir("./assets/crates").unwrap() {
+        for path in fs::read_dir(assets.join("crates")).unwrap() {
             crate_tex.push(path.unwrap().file_name().into_string().unwrap());
         }
 
         let mut c_tex = HashMap::new();
         let mut b_tex = HashMap::new();
         let mut t_tex = HashMap::new();
-        for tex in crate_tex {
-            c_tex.insert(
-                tex.to_owned(),
-                Arc::new(RwLock::new(
-                    Tex::from_path(format!("./assets/crates/{}", tex), &TextureSettings::new())
-                        .unwrap(),
-                )),
-            );
-
-            b_tex.insert(
-                tex.to_owned(),
-                Arc::new(RwLock::new(
-                    Tex::from_path(format!("./assets/blocked/{}", tex), &TextureSettings::new())
-                        .unwrap(),
-                )),
-            );
-            t_tex.insert(
-                tex.to_owned(),
-                Arc::new(RwLock::new(
-                    Tex::from_path(format!("./assets/targets/{}", tex), &TextureSettings::new())
-                        .unwrap(),
-                )),
-            );
+        for tex in crate_tex.iter() {
+            c_tex.insert(tex.to_owned(), load(assets.join("crates").join(tex)));
+            b_tex.insert(tex.to_owned(), load(assets.join("blocked").join(tex)));
+            t_tex.insert(tex.to_owned(), load(assets.join("targets").join(tex)));
+        }
+        let mut tile_tex = HashMap::new();
+        for kind in tiles::KINDS.iter() {
+            if kind.colored() {
+                for tex in crate_tex.iter() {
+                    let file = assets.join("tiles").join(kind.sprite()).join(tex);
+                    tile_tex.insert(tiles::colored(*kind, tex), load(file));
+                }
+            } else {
+                let file = format!("{}.png", kind.sprite());
+                tile_tex.insert(
+                    kind.sprite().to_string(),
+                    load(assets.join("tiles").join(file)),
+                );
+            }
         }
         let obj_tex = GameTextures {
-            wall: Arc::new(RwLock::new(
-                Tex::from_path("./assets/wall.png", &TextureSettings::new()).unwrap(),
-            )),
-            floor: Arc::new(RwLock::new(
-                Tex::from_path("./assets/floor.png", &TextureSettings::new()).unwrap(),
-            )),
+            wall: load(assets.join("wall.png")),
+            floor: load(assets.join("floor.png")),
             _crate: c_tex,
             b_crate: b_tex,
             targets: t_tex,
+            tiles: tile_tex,
         };
 
-        let player_tex = PlayerTextures {
-            player_n: Arc::new(RwLock::new(
-                Tex::from_path("./assets/player_n.png", &TextureSettings::new()).unwrap(),
-            )),
-            player_s: Arc::new(RwLock::new(
-                Tex::from_path("./assets/player_s.png", &TextureSettings::new()).unwrap(),
-            )),
-            player_e: Arc::new(RwLock::new(
-                Tex::from_path("./assets/player_e.png", &TextureSettings::new()).unwrap(),
-            )),
-            player_w: Arc::new(RwLock::new(
-                Tex::from_path("./assets/player_w.png", &TextureSettings::new()).unwrap(),
-            )),
+        let frames = |name: &str, sideways: bool| {
+            let frame = |tex| Frame {
+                tex: tex,
+                mirrored: false,
+                squat: 0.,
+            };
+            let mut frames = vec![frame(load(assets.join(format!("{}.png", name))))];
+            for i in 1.. {
+                let path = assets.join(format!("{}_{}.png", name, i));
+                if !path.exists() {
+                    break;
+                }
+                frames.push(frame(load(path)));
+            }
+            if frames.len() == 1 {
+                let mut step = frames[0].clone();
+                if sideways {
+                    step.squat = 0.06;
+                } else {
+                    step.mirrored = true;
+                }
+                frames.push(step);
+            }
+            frames
         };
-        let aux_sprite = Some(player_tex.player_n.clone());
-        let player = Player {
-            sprite: aux_sprite,
-            position: Position::new(1, 3).unwrap(),
-            canMove: false,
+        let player_tex = PlayerTextures {
+            player_n: frames("player_n", false),
+            player_s: frames("player_s", false),
+            player_e: frames("player_e", true),
+            player_w: frames("player_w", true),
         };
-        c_matrix.coll[(
-            player.position.get_y() as usize,
-            player.position.get_x() as usize,
-        )] = true;
         Game {
-            gl: Some(GlGraphics::new(OpenGL::V3_2)),
-            floor: Vec::new(),
-            special: Vec::new(),
-            player: player,
-            obj_tex: Some(obj_tex),
-            player_tex: Some(player_tex),
-            start_t: start,
-            score: score,
-            targets_left: -1,
-            size: (size.0 as u32, size.1 as u32),
+            gl: GlGraphics::new(OpenGL::V3_2),
+            state: state,
+            obj_tex: obj_tex,
+            player_tex: player_tex,
+            camera: Camera::new(),
+            animation: Animation::new(0.),
+            view: [1., 1.],
+            cursor: [0., 0.],
+            drag: None,
+            session: Session::new(Progress::load_default().unwrap_or_else(|e| {
+                eprintln!("could not read progress: {}", e);
+                Progress::new()
+            })),
+            ctrl: false,
+            hint: None,
+            autoplay: Vec::new(),
+            autoplay_wait: 0.,
+            autoplay_step: AUTOPLAY_STEP,
+            paused: false,
+            mode: Mode::Play,
+            editor: None,
+            brush: Brush::Wall,
+            painting: None,
+            solver_limit: solver_limit,
+            assets: assets.to_path_buf(),
         }
     }
     fn move_player(&mut self, key: Key) {
-        let pos = &self.player.position.clone();
-        self.check(&pos, key);
-
-        match key {
-            Key::Up => {
-                if let Some(ref spr) = self.player_tex {
-                    self.player.sprite = Some(spr.player_n.clone());
-                }
-            }
-            Key::Down => {
-                if let Some(ref spr) = self.player_tex {
-                    self.player.sprite = Some(spr.player_s.clone());
-                }
+        if let Some(dir) = direction(key) {
+            self.step(dir);
+        }
+    }
+    /// Moves the player, or waits for the move being drawn to finish.
+    fn step(&mut self, dir: Direction) {
+        if self.animation.is_moving() {
+            return self.animation.queue.push_back(dir);
+        }
+        let mut from = self.state.player.position;
+        let next = from + dir.offset();
+        let moved = self.state.move_player(dir);
+        let to = self.state.player.position;
+        if to != next {
+            // teleported, appears there at once
+            from = to;
+        }
+        match moved {
+            Move::Blocked => (),
+            Move::Walk => self.animation.start(from, to, None),
+            Move::Push => {
+                // on ice the crate slides further than the step
+                let pushed = self.state.last_pushed().map(|p| (next, p));
+                self.animation.start(from, to, pushed);
             }
-            Key::Left => {
-                if let Some(ref spr) = self.player_tex {
-                    self.player.sprite = Some(spr.player_w.clone());
-                }
+        }
+    }
+    fn generate(&mut self, seed: Option<u64>) {
+        self.animation.stop();
+        let metrics = match seed {
+            Some(seed) => self.state.gen_seeded(seed),
+            None => self.state.gen_level(),
+        };
+        self.hint = Some(format!(
+            "Generated, at least {} pushes, {} box lines, {} box changes from solved",
+            metrics.pushes, metrics.box_lines, metrics.box_changes
+        ));
+    }
+    /// Shows `message` if there's one, keeping the hint otherwise.
+    fn note(&mut self, message: Option<String>) {
+        if message.is_some() {
+            self.hint = message;
+        }
+    }
+    fn next_level(&mut self) {
+        self.animation.stop();
+        let message = self.session.next_level(&mut self.state);
+        self.note(message);
+    }
+    fn skip_level(&mut self, by: isize) {
+        self.animation.stop();
+        let message = self.session.skip_level(by, &mut self.state);
+        self.note(message);
+    }
+    /// Answers "generate a new level?".
+    fn confirm(&mut self, key: Key) {
+        self.hint = None;
+        // the seed of the new level shows in the HUD
+        if self.session.answer_generate(key == Key::Y, &mut self.state) {
+            self.animation.stop();
+        }
+        if key == Key::LCtrl || key == Key::RCtrl {
+            self.ctrl = true;
+        }
+    }
+    fn show_hint(&mut self) {
+        self.hint = Some(match solve(&self.state, self.solver_limit) {
+            Ok(ref sol) if !sol.pushes.is_empty() => {
+                let push = &sol.pushes[0];
+                let color = self
+                    .state
+                    .special
+                    .iter()
+                    .find(|o| o.obj_type == ObjectType::Crate && o.position == push.position)
+                    .and_then(|o| o.sprite.clone())
+                    .unwrap_or_default();
+                format!(
+                    "Hint: push {} crate at ({}, {}) {:?}",
+                    color.trim_end_matches(".png"),
+                    push.position.get_x(),
+                    push.position.get_y(),
+                    push.direction
+                )
             }
-            Key::Right => {
-                if let Some(ref spr) = self.player_tex {
-                    self.player.sprite = Some(spr.player_e.clone());
-                }
+            Ok(_) => "Hint: nothing left to push".to_string(),
+            Err(e) => format!("Hint: {}", e),
+        });
+    }
+    fn toggle_autoplay(&mut self) {
+        if !self.autoplay.is_empty() {
+            self.autoplay.clear();
+            return;
+        }
+        match solve(&self.state, self.solver_limit) {
+            Ok(sol) => self.play(sol.moves),
+            Err(e) => self.hint = Some(format!("Hint: {}", e)),
+        }
+    }
+    fn play(&mut self, moves: Vec<Direction>) {
+        self.autoplay = moves;
+        self.autoplay.reverse();
+        self.autoplay_wait = 0.;
+        self.paused = false;
+    }
+    /// Checks `replay` against its level and plays it back.
+    fn open_replay(&mut self, replay: &Replay) {
+        let size = (self.state.size.0 as usize, self.state.size.1 as usize);
+        let mut check = GameState::new(size, Vec::new());
+        check.rule_set = self.state.rule_set;
+        check.monochrome = self.state.monochrome;
+        self.hint = Some(match replay.verify(&mut check) {
+            Ok(Verdict::Solved) => format!("Replay solves the level in {}", check.score),
+            Ok(Verdict::Unsolved) => format!("Replay leaves the level unsolved, {}", check.score),
+            Err(e) => format!("Replay doesn't match the level: {}", e),
+        });
+        self.session.pack = None;
+        self.animation.stop();
+        self.state.load_level(&replay.level);
+        self.play(replay.steps.iter().map(|s| s.direction).collect());
+    }
+    /// Saves the moves made on this level under the data dir.
+    fn save_replay(&mut self) {
+        let replay = match Replay::record(&self.state) {
+            Some(r) => r,
+            None => return,
+        };
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let dir = data_dir().unwrap_or_default().join("replays");
+        let path = dir.join(format!("{}.txt", secs));
+        let saved = fs::create_dir_all(&dir)
+            .map_err(|e| e.to_string())
+            .and_then(|_| replay.save(&path).map_err(|e| e.to_string()));
+        self.hint = Some(match saved {
+            Ok(()) => format!("Replay saved to {}", path.display()),
+            Err(e) => format!("Could not save the replay: {}", e),
+        });
+    }
+    /// Writes the board as it stands to a new level file.
+    fn save_board(&mut self) {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let dir = data_dir().unwrap_or_default().join("levels");
+        let path = dir.join(format!("{}.txt", secs));
+        let saved = fs::create_dir_all(&dir)
+            .map_err(|e| e.to_string())
+            .and_then(|_| {
+                save_collection(&path, &[self.state.to_level()]).map_err(|e| e.to_string())
+            });
+        self.hint = Some(match saved {
+            Ok(()) => format!("Board saved to {}", path.display()),
+            Err(e) => format!("Could not save the board: {}", e),
+        });
+    }
+    /// Keys controlling auto-play, returns whether `key` was one.
+    fn playback(&mut self, key: Key) -> bool {
+        if self.autoplay.is_empty() {
+            return false;
+        }
+        match key {
+            Key::Space => self.paused = !self.paused,
+            Key::Period if self.paused => {
+                let dir = self.autoplay.pop().unwrap();
+                self.step(dir);
             }
-            _ => (),
+            Key::LeftBracket => self.autoplay_step = (self.autoplay_step * 1.5).min(2.),
+            Key::RightBracket => self.autoplay_step = (self.autoplay_step / 1.5).max(0.01),
+            _ => return false,
         }
+        true
     }
-    fn check(&mut self, position: &Position, key: Key) {
-        use Key::*;
-
-        let mut next = (*position).clone();
-        let mut crate_found = -1;
-        let mut target_found = -1;
-        let mut success = true;
-        let dir = |mut pos| {
-            match key {
-                Down => pos = pos + Position::new(0, 1).unwrap(),
-                Up => pos = pos + Position::new(0, -1).unwrap(),
-                Left => pos = pos + Position::new(-1, 0).unwrap(),
-                Right => pos = pos + Position::new(1, 0).unwrap(),
-                _ => (),
+    fn press(&mut self, key: Key) {
+        match key {
+            Key::Equals | Key::Plus | Key::NumPadPlus => return self.camera.zoom_in(),
+            Key::Minus | Key::NumPadMinus => return self.camera.zoom_out(),
+            Key::D0 | Key::NumPad0 => return self.camera.reset(),
+            _ => (),
+        }
+        if self.session.asking() {
+            return self.confirm(key);
+        }
+        if self.mode == Mode::Edit {
+            return self.edit_key(key);
+        }
+        if self.playback(key) {
+            return;
+        }
+        if key != Key::H && key != Key::A {
+            self.autoplay.clear();
+            self.hint = None;
+        }
+        match key {
+            Key::H => self.show_hint(),
+            Key::A => self.toggle_autoplay(),
+            Key::LCtrl | Key::RCtrl => self.ctrl = true,
+            Key::U => {
+                self.animation.stop();
+                self.state.undo();
             }
-            pos
-        };
-
-        next = dir(next);
-        let mut crate_type = String::new();
-
-        {
-            let obj = self
-                .special
-                .iter()
-                .enumerate()
-                .filter(|x| !(x.1.obj_type == ObjectType::Target))
-                .find(|x| x.1.position == next);
-
-            if let Some(ele) = obj {
-                success = false;
-                if ele.1.obj_type == ObjectType::Crate {
-                    crate_found = ele.0 as i32;
-                    if let Some(ref spr) = ele.1.sprite {
-                        crate_type = spr.0.clone();
-                    }
-                }
+            Key::Z if self.ctrl => {
+                self.animation.stop();
+                self.state.undo();
             }
-
-            if success {
-                self.player.position = next;
-                self.score.moves += 1;
-            } else {
-                success = true;
-                next = dir(next);
+            Key::Y if self.ctrl => {
+                self.animation.stop();
+                self.state.redo();
             }
-
-            if crate_found != -1 {
-                let cr = self
-                    .special
-                    .iter()
-                    .enumerate()
-                    .find(|x| x.1.position == next);
-                if let Some(ele) = cr {
-                    if ele.1.obj_type == ObjectType::Target
-                        && ele.1.sprite.as_ref().unwrap().0 == crate_type
-                    {
-                        target_found = ele.0 as i32;
-                    } else {
-                        success = false;
-                    }
-                }
+            Key::S if self.ctrl => self.save_replay(),
+            Key::B => self.save_board(),
+            Key::R => {
+                self.animation.stop();
+                self.state.restart();
             }
-        }
-        if success && crate_found != -1 {
-            self.special.get_mut(crate_found as usize).unwrap().position = next;
-
-            if target_found != -1 {
-                {
-                    let mut crt = self.special.get_mut(crate_found as usize).unwrap();
-                    crt.obj_type = ObjectType::Blocking;
-
-                    if let Some(ref spr) = self.obj_tex {
-                        crt.sprite = Some((
-                            crate_type.clone(),
-                            spr.b_crate.get(&crate_type).unwrap().clone(),
-                        ));
-                    }
-                }
-                self.score.scored += 1;
-                self.targets_left -= 1;
-                self.special.remove(target_found as usize);
-                if self.targets_left == 0 {
-                    println!("END");
-                    self.gen_level();
-                }
+            Key::E | Key::T if self.mode == Mode::Test => self.edit(),
+            Key::E => {
+                let editor = match self.state.level {
+                    Some(ref level) => Editor::from_level(level),
+                    None => Editor::new((self.state.size.0 as usize, self.state.size.1 as usize)),
+                };
+                self.open_editor(editor);
             }
+            Key::N => self.skip_level(1),
+            Key::P => self.skip_level(-1),
+            Key::G if self.mode == Mode::Play => self.hint = Some(self.session.ask_generate()),
+            _ => self.move_player(key),
         }
     }
-
-    fn render(&mut self, args: &RenderArgs) {
-        if let Some(ref mut gl) = self.gl {
-            let iter = self.floor.iter().chain(self.special.iter());
-            let player = &self.player;
-
-            let mut glyphs: GlyphCache =
-                GlyphCache::new("assets/FiraSans-Regular.ttf", (), TextureSettings::new()).unwrap();
-            let time = SystemTime::now()
-                .duration_since(self.start_t)
+    /// Crate colors there are textures for, in order.
+    fn colors(&self) -> Vec<String> {
+        let mut colors: Vec<String> = self.obj_tex._crate.keys().cloned().collect();
+        colors.sort();
+        if colors.is_empty() {
+            colors.push(DEFAULT_COLOR.to_string());
+        }
+        colors
+    }
+    fn open_editor(&mut self, editor: Editor) {
+        self.session.pack = None;
+        self.autoplay.clear();
+        self.editor = Some(editor);
+        self.edit();
+    }
+    /// Back to building the level, showing the board as it is.
+    fn edit(&mut self) {
+        self.mode = Mode::Edit;
+        self.animation.stop();
+        if let Some(ref editor) = self.editor {
+            self.state.load_level(&editor.preview());
+        }
+    }
+    /// Why the level being built can't be played yet, if it can't.
+    fn show_problems(&mut self) -> bool {
+        let problems = self
+            .editor
+            .as_ref()
+            .map(|e| e.problems())
+            .unwrap_or_default();
+        if !problems.is_empty() {
+            self.hint = Some(problems.join(", "));
+        }
+        !problems.is_empty()
+    }
+    fn check_level(&mut self) {
+        if self.show_problems() {
+            return;
+        }
+        self.hint = Some(match solve(&self.state, self.solver_limit) {
+            Ok(sol) => format!(
+                "Solvable in {} pushes, {} moves",
+                sol.pushes.len(),
+                sol.moves.len()
+            ),
+            Err(SolveError::NotFound) => "No solution found within the solver limit".to_string(),
+            Err(e) => format!("Not checked, {}", e),
+        });
+    }
+    /// Saves the level being built to its file, or to a new one under the
+    /// data dir, once it's playable.
+    fn save_level(&mut self) {
+        let editor = match self.editor {
+            Some(ref mut editor) => editor,
+            None => return,
+        };
+        if let Some(problem) = editor.problems().into_iter().next() {
+            self.hint = Some(format!("Not saved, {}", SaveError::Unplayable(problem)));
+            return;
+        }
+        if editor.file.is_none() {
+            let secs = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
                 .unwrap()
                 .as_secs();
-            let score;
-            let aux = 100 * self.score.scored - (self.score.moves) - time as i32;
-
-            if aux < 0 {
-                score = 0;
-            } else {
-                score = aux;
+            let dir = data_dir().unwrap_or_default().join("levels");
+            if let Err(e) = fs::create_dir_all(&dir) {
+                self.hint = Some(format!("Could not save the level: {}", e));
+                return;
             }
-            let t = self.targets_left;
+            editor.file = Some((dir.join(format!("{}.txt", secs)), 0));
+        }
+        self.hint = Some(match editor.save() {
+            Ok(path) => format!("Level saved to {}", path.display()),
+            Err(e) => format!("Could not save the level: {}", e),
+        });
+    }
 
-            gl.draw(args.viewport(), |c, g| {
-                clear([1.0, 1.0, 1.0, 1.0], g);
-                for img in iter {
-                    let pos = &img.position;
-                    let transform = c
-                        .transform
-                        .trans((pos.get_x() * 64) as f64, (pos.get_y() * 64) as f64);
-                    if let Some(ref spr) = img.sprite {
-                        image(&(*spr.1.read().unwrap()), transform, g);
-                    }
-                }
-                if let Some(ref spr) = player.sprite {
-                    image(
-                        &(*(spr.read().unwrap())),
-                        c.transform.trans(
-                            (player.position.get_x() * 64) as f64,
-                            (player.position.get_y() * 64) as f64,
-                        ),
-                        g,
-                    );
-                }
-                text::Text::new_color([0., 1., 0., 1.], 64).draw(
-                    &format!("Score: {:?} Time: {:?} T: {}", score, time, t),
-                    &mut glyphs,
-                    &c.draw_state,
-                    c.transform.trans(0., 64. * 11. - 11.),
-                    g,
-                );
-            });
+    /// Color of the crate, target or colored tile brush, the first color
+    /// for the others.
+    fn brush_color(&self) -> String {
+        match self.brush {
+            Brush::Crate(ref c) | Brush::Target(ref c) | Brush::Tile(_, Some(ref c)) => c.clone(),
+            _ => self.colors()[0].clone(),
         }
     }
-    fn update(&mut self, args: &UpdateArgs) {}
-    fn gen_level(&mut self) {
-        self.special.clear();
-        self.floor.clear();
-        let mut c_matrix = CollisionMatrix {
-            coll: DMatrix::<bool>::from_element(self.size.1 as usize, self.size.0 as usize, false),
+    fn edit_key(&mut self, key: Key) {
+        let (width, height) = self.editor.as_ref().unwrap().size();
+        let resize = match key {
+            Key::Left => (width - 1, height),
+            Key::Right => (width + 1, height),
+            Key::Up => (width, height - 1),
+            Key::Down => (width, height + 1),
+            _ => (width, height),
         };
-        let mut rand = rand::thread_rng();
-        let mut crates = Vec::new();
-        let mut obj;
-        //walls / floors
-        for i in 0..self.size.0 as i32 {
-            for j in 0..self.size.1 as i32 {
-                if i == 0 || i == self.size.0 as i32 - 1 || j == 0 || j == self.size.1 as i32 - 1 {
-                    if let Some(ref g) = self.gl {
-                        obj = Object::new(
-                            Position::new(i, j).unwrap(),
-                            ObjectType::Blocking,
-                            Some((
-                                "wall".to_string(),
-                                self.obj_tex.as_ref().unwrap().wall.clone(),
-                            )),
-                        );
-                    } else {
-                        obj = Object::new(Position::new(i, j).unwrap(), ObjectType::Blocking, None);
-                    }
-                    self.special.push(obj);
+        if resize != (width, height) {
+            self.editor.as_mut().unwrap().resize(resize.0, resize.1);
+            return self.edit();
+        }
+        self.hint = None;
+        match key {
+            Key::D1 => self.brush = Brush::Wall,
+            Key::D2 => self.brush = Brush::Floor,
+            Key::D3 => self.brush = Brush::Crate(self.brush_color()),
+            Key::D4 => self.brush = Brush::Target(self.brush_color()),
+            Key::D5 => self.brush = Brush::Player,
+            Key::D6 => self.brush = Brush::Erase,
+            Key::D7 => {
+                // the next kind of special tile
+                let next = match self.brush {
+                    Brush::Tile(symbol, _) => tiles::KINDS
+                        .iter()
+                        .position(|k| k.symbol() == symbol)
+                        .map_or(0, |i| i + 1),
+                    _ => 0,
+                };
+                let kind = tiles::KINDS[next % tiles::KINDS.len()];
+                let color = if kind.colored() {
+                    Some(self.brush_color())
                 } else {
-                    if let Some(ref g) = self.gl {
-                        obj = Object::new(
-                            Position::new(i, j).unwrap(),
-                            ObjectType::Passing,
-                            Some((
-                                "floor".to_string(),
-                                self.obj_tex.as_ref().unwrap().floor.clone(),
-                            )),
-                        );
-                    } else {
-                        obj = Object::new(Position::new(i, j).unwrap(), ObjectType::Passing, None);
-                    }
-
-                    self.floor.push(obj);
-                }
+                    None
+                };
+                self.brush = Brush::Tile(kind.symbol(), color);
             }
-        }
-
-        let n_crates: usize = rand.gen_range((3..9));
-
-        //vec of crate colors (eg: red, blue, green...)
-        let mut elems = Vec::new();
-        if let Some(ref obj) = self.obj_tex {
-            let key_iter = obj._crate.keys();
-            for ele in key_iter {
-                elems.push(ele.to_owned());
+            Key::C => {
+                let colors = self.colors();
+                let color = self.brush_color();
+                let next = colors.iter().position(|c| *c == color).map_or(0, |i| i + 1);
+                let color = colors[next % colors.len()].clone();
+                self.brush = match self.brush {
+                    Brush::Target(_) => Brush::Target(color),
+                    Brush::Tile(symbol, Some(_)) => Brush::Tile(symbol, Some(color)),
+                    _ => Brush::Crate(color),
+                };
             }
-        }
-        //hash map of crate numbers
-        let mut crate_numbers = HashMap::new();
-        let mut crate_n = crate_numbers.clone();
-        'l: loop {
-            for elem in elems.iter() {
-                {
-                    let entry = crate_numbers.entry(elem.clone()).or_insert(0);
-                    *entry += rand.gen_range((0..2));
-                }
-                if crate_numbers.values().sum::<usize>() >= n_crates {
-                    break 'l;
-                }
+            Key::V => self.check_level(),
+            Key::T if !self.show_problems() => {
+                self.mode = Mode::Test;
+                self.state.restart();
             }
+            Key::E if !self.show_problems() => {
+                self.mode = Mode::Play;
+                self.editor = None;
+                self.state.restart();
+            }
+            Key::S if self.ctrl => self.save_level(),
+            Key::LCtrl | Key::RCtrl => self.ctrl = true,
+            _ => (),
         }
-        crate_numbers = crate_numbers.into_iter().filter(|&(_, v)| v != 0).collect();
-
-        let mut loop_fails = 0;
-        //crate loop
-        'l: loop {
-            'out: for i in 2..self.size.0 as i32 - 2 {
-                'ins: for j in 2..self.size.1 as i32 - 2 {
-                    let r: f32 = rand.gen_range((0. ..1.));
-                    if r > 0.60 && c_matrix.coll[(j as usize, i as usize)] == false {
-                        let mut dist = 3 - loop_fails / 30;
-                        if dist < 0 {
-                            dist = 0;
-                        }
-
-                        if c_matrix.check_surroundings((j as usize, i as usize), dist) {
-                            loop_fails += 1;
-
-                            continue;
-                        }
-                        loop_fails = 0;
-                        let tex = crate_numbers.keys().choose(&mut rand).unwrap();
+    }
+    fn paint_at_cursor(&mut self) {
+        let (brush, tile) = match (self.painting.clone(), self.tile_at_cursor()) {
+            (Some(brush), Some(tile)) => (brush, tile),
+            _ => return,
+        };
+        if let Some(ref mut editor) = self.editor {
+            editor.paint(tile.get_x() as usize, tile.get_y() as usize, &brush);
+        }
+        self.edit();
+    }
+    fn move_cursor(&mut self, cursor: [f64; 2]) {
+        self.cursor = cursor;
+        if self.mode == Mode::Edit {
+            self.paint_at_cursor();
+        }
+    }
+    /// Tile under the mouse cursor.
+    fn tile_at_cursor(&self) -> Option<Position> {
+        if self.cursor[1] > self.view[1] {
+            return None;
+        }
+        let board = self.state.dimensions;
+        let at = self.camera.to_board(self.cursor, self.view, board);
+        Position::on_board(at[0].floor() as i32, at[1].floor() as i32, board).ok()
+    }
+    fn mouse_press(&mut self, button: MouseButton) {
+        if self.mode == Mode::Edit {
+            self.painting = match button {
+                MouseButton::Left => Some(self.brush.clone()),
+                MouseButton::Right => Some(Brush::Erase),
+                _ => None,
+            };
+            return self.paint_at_cursor();
+        }
+        if button == MouseButton::Left {
+            self.drag = self.tile_at_cursor();
+        }
+    }
+    /// Walks to the tile clicked, or takes a crate dragged to the tile the
+    /// button was let go on.
+    fn mouse_release(&mut self, button: MouseButton) {
+        if self.mode == Mode::Edit {
+            self.painting = None;
+            return;
+        }
+        if button != MouseButton::Left {
+            return;
+        }
+        let (from, to) = match (self.drag.take(), self.tile_at_cursor()) {
+            (Some(from), Some(to)) => (from, to),
+            _ => return,
+        };
+        let dragged_crate = from != to
+            && self
+                .state
+                .special
+                .iter()
+                .any(|o| o.position == from && o.obj_type == ObjectType::Crate);
+        let moves = if dragged_crate {
+            path::push_to(&self.state, from, to)
+        } else {
+            path::walk(&self.state, to)
+        };
+        self.hint = None;
+        match moves {
+            Some(moves) => self.play(moves),
+            None => self.hint = Some("Can't get there".to_string()),
+        }
+    }
+    fn release(&mut self, key: Key) {
+        if key == Key::LCtrl || key == Key::RCtrl {
+            self.ctrl = false;
+        }
+    }
 
-                        crate_n.entry(tex.to_owned()).or_insert(0);
+    fn render(&mut self, args: &RenderArgs) {
+        let state = &self.state;
+        let obj_tex = &self.obj_tex;
+        // targets first, crates standing on them are drawn over them
+        let is_target = |o: &&Object| o.obj_type == ObjectType::Target;
+        let iter = state
+            .floor
+            .iter()
+            .chain(state.special.iter().filter(is_target))
+            .chain(state.special.iter().filter(|o| !is_target(o)));
+        let animation = &self.animation;
+        let frames = self.player_tex.facing(state.player.facing);
+        let player = &frames[animation.frame(frames.len())];
 
-                        if crate_n[tex] == crate_numbers[tex] {
-                            continue;
-                        }
-                        *crate_n.get_mut(tex).unwrap() += 1;
+        let mut glyphs: GlyphCache = GlyphCache::new(
+            self.assets.join("FiraSans-Regular.ttf"),
+            (),
+            TextureSettings::new(),
+        )
+        .unwrap();
+        let time = SystemTime::now()
+            .duration_since(state.start_t)
+            .unwrap()
+            .as_secs();
+        let score = state.points();
+        let t = state.targets_left;
+        let counters = &state.score;
+        let hint = &self.hint;
+        let deadlock = &state.deadlock;
+        let seed_line = state.seed.map(|seed| format!("Seed {}", seed));
+        let pack_line = self.session.pack.as_ref().map(|pack| {
+            let best = match self.session.progress.record(&pack.name, pack.index) {
+                Some(r) if r.solved => format!(
+                    " - best {} moves, {} pushes, {}s",
+                    r.moves.unwrap_or(0),
+                    r.pushes.unwrap_or(0),
+                    r.time.unwrap_or(0)
+                ),
+                _ => String::new(),
+            };
+            format!("Level {}/{}{}", pack.index + 1, pack.levels.len(), best)
+        });
+        let info_line = match self.mode {
+            Mode::Test => Some("Testing the level, T goes back to the editor".to_string()),
+            _ => pack_line.or(seed_line),
+        };
+        let (status, details) = match (self.mode, self.editor.as_ref()) {
+            (Mode::Edit, Some(editor)) => {
+                let (width, height) = editor.size();
+                (
+                    format!("Editing {}x{} - {}", width, height, self.brush.name()),
+                    EDITOR_HELP.to_string(),
+                )
+            }
+            _ => (
+                format!("Score: {:?} Time: {:?} T: {}", score, time, t),
+                format!(
+                    "Moves: {}  Pushes: {}  Box lines: {}  Box changes: {}",
+                    counters.moves, counters.pushes, counters.box_lines, counters.box_changes
+                ),
+            ),
+        };
+        let show_player = match (self.mode, self.editor.as_ref()) {
+            (Mode::Edit, Some(editor)) => editor.player().is_some(),
+            _ => true,
+        };
+        let deadlocked = deadlock.is_some() && self.mode != Mode::Edit;
 
-                        if let Some(ref g) = self.gl {
-                            crates.push(Object::new(
-                                Position::new(i, j).unwrap(),
-                                ObjectType::Crate,
-                                Some((
-                                    tex.to_string(),
-                                    self.obj_tex
-                                        .as_ref()
-                                        .unwrap()
-                                        ._crate
-                                        .get(tex)
-                                        .unwrap()
-                                        .clone(),
-                                )),
-                            ));
-                        } else {
-                            crates.push(Object::new(
-                                Position::new(i, j).unwrap(),
-                                ObjectType::Crate,
-                                None,
-                            ));
-                        }
-                        c_matrix.coll[(j as usize, i as usize)] = true;
-                        if crate_n == crate_numbers {
-                            break 'l;
-                        }
+        let window = args.window_size;
+        let hud = HUD_HEIGHT as f64;
+        let view = [window[0], (window[1] - hud).max(1.)];
+        let board = state.dimensions;
+        let player_pos = animation.player(state.player.position);
+        self.camera.follow(player_pos, view, board);
+        self.view = view;
+        let camera = &self.camera;
+        let scale = camera.tile_size(view, board) / TILE as f64;
+        let at = |pos: [f64; 2]| camera.to_screen(pos[0], pos[1], view, board);
 
-                        if rand.gen() {
-                            continue 'out;
-                        } else {
-                            continue 'ins;
-                        }
+        self.gl.draw(args.viewport(), |c, g| {
+            clear([1.0, 1.0, 1.0, 1.0], g);
+            for img in iter {
+                let pos = &img.position;
+                // only crates slide, locked ones included, targets under
+                // them stay put
+                let screen = match img.obj_type {
+                    ObjectType::Crate | ObjectType::Blocking => at(animation.object(*pos)),
+                    _ => at([pos.get_x() as f64, pos.get_y() as f64]),
+                };
+                let transform = c.transform.trans(screen[0], screen[1]).zoom(scale);
+                let stuck = match *deadlock {
+                    Some(ref d) => img.obj_type == ObjectType::Crate && d.crates.contains(pos),
+                    None => false,
+                };
+                if let Some(spr) = obj_tex.sprite(img, state.placed(img)) {
+                    if stuck {
+                        Image::new_color([1., 0.35, 0.35, 1.]).draw(
+                            &(*spr.read().unwrap()),
+                            &c.draw_state,
+                            transform,
+                            g,
+                        );
+                    } else {
+                        image(&(*spr.read().unwrap()), transform, g);
                     }
                 }
             }
-        }
-
-        let mut _targets = HashMap::new();
-        let mut targets = 0;
-        let crates_ = crates.len();
-        let mut target = Vec::with_capacity(crates_);
-        'l: loop {
-            for i in 1..self.size.0 as i32 - 2 {
-                for j in 1..self.size.1 as i32 - 2 {
-                    let r: f32 = rand.gen_range((0. ..1.));
-                    if r > 0.90 && c_matrix.coll[(j as usize, i as usize)] == false {
-                        let mut dist = 3 - loop_fails / 30;
-                        if dist < 0 {
-                            dist = 0;
-                        }
-                        if c_matrix.check_surroundings((j as usize, i as usize), dist) {
-                            loop_fails += 1;
-
-                            continue;
-                        }
-
-                        loop_fails = 0;
-                        let tex = crate_numbers.keys().choose(&mut rand).unwrap();
-
-                        _targets.entry(tex.to_owned()).or_insert(0);
-                        if _targets[tex] == crate_numbers[tex] {
-                            continue;
-                        }
-
-                        *_targets.get_mut(tex).unwrap() += 1;
-
-                        if let Some(ref g) = self.gl {
-                            crates.push(Object::new(
-                                Position::new(i, j).unwrap(),
-                                ObjectType::Target,
-                                Some((
-                                    tex.to_string(),
-                                    self.obj_tex
-                                        .as_ref()
-                                        .unwrap()
-                                        .targets
-                                        .get(tex)
-                                        .unwrap()
-                                        .clone(),
-                                )),
-                            ));
-                        } else {
-                            crates.push(Object::new(
-                                Position::new(i, j).unwrap(),
-                                ObjectType::Target,
-                                None,
-                            ));
-                        }
-
-                        targets += 1;
-                        c_matrix.coll[(j as usize, i as usize)] = true;
-                        if _targets == crate_numbers {
-                            break 'l;
-                        }
-                    }
+            if show_player {
+                let screen = at(player_pos);
+                let tile = TILE as f64;
+                let mut transform = c
+                    .transform
+                    .trans(screen[0], screen[1])
+                    .zoom(scale)
+                    .trans(0., tile * player.squat)
+                    .scale(1., 1. - player.squat);
+                if player.mirrored {
+                    transform = transform.trans(tile, 0.).flip_h();
                 }
+                image(&(*(player.tex.read().unwrap())), transform, g);
+            }
+            rectangle(
+                [1.0, 1.0, 1.0, 1.0],
+                [0., view[1], window[0], hud],
+                c.transform,
+                g,
+            );
+            text::Text::new_color([0., 1., 0., 1.], 30).draw(
+                &status,
+                &mut glyphs,
+                &c.draw_state,
+                c.transform.trans(8., view[1] + 30.),
+                g,
+            );
+            text::Text::new_color([0., 0., 0., 1.], 18).draw(
+                &details,
+                &mut glyphs,
+                &c.draw_state,
+                c.transform.trans(8., view[1] + 52.),
+                g,
+            );
+            if let Some(ref line) = info_line {
+                text::Text::new_color([0., 0., 0., 1.], 24).draw(
+                    line,
+                    &mut glyphs,
+                    &c.draw_state,
+                    c.transform.trans(8., view[1] - 8.),
+                    g,
+                );
             }
+            if deadlocked {
+                text::Text::new_color([1., 0., 0., 1.], 24).draw(
+                    "Deadlocked - undo or restart",
+                    &mut glyphs,
+                    &c.draw_state,
+                    c.transform.trans(8., 52.),
+                    g,
+                );
+            }
+            if let Some(ref hint) = *hint {
+                text::Text::new_color([1., 1., 0., 1.], 24).draw(
+                    hint,
+                    &mut glyphs,
+                    &c.draw_state,
+                    c.transform.trans(8., 24.),
+                    g,
+                );
+            }
+        });
+    }
+    fn update(&mut self, args: &UpdateArgs) {
+        self.animation.update(args.dt);
+        if self.animation.is_moving() {
+            return;
         }
-        println!(
-            "final result: crates: {:?}\n targets: {:?}\n",
-            crate_n, _targets
-        );
-        target.append(&mut crates);
-        self.special.append(&mut target);
-        self.targets_left = targets as i32;
-        for i in 0..c_matrix.coll.nrows() {
-            println!("{}: ", i);
-            for j in 0..c_matrix.coll.ncols() {
-                print!("[{}]", c_matrix.coll[(i as usize, j as usize)]);
+        if self.mode != Mode::Edit && self.state.is_solved() {
+            if self.mode == Mode::Test {
+                self.hint = Some(format!("Solved in {}", self.state.score));
+                return self.edit();
             }
-            println!("\n");
+            println!("END");
+            return self.next_level();
+        }
+        if let Some(dir) = self.animation.queue.pop_front() {
+            return self.step(dir);
+        }
+        if self.autoplay.is_empty() || self.paused {
+            return;
+        }
+        self.autoplay_wait -= args.dt;
+        if self.autoplay_wait <= 0. {
+            let dir = self.autoplay.pop().unwrap();
+            self.step(dir);
+            self.autoplay_wait = self.autoplay_step;
         }
     }
 }
 
 fn main() {
-    let size = (15, 10);
+    let opts = match cli::parse(env::args().skip(1)) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            process::exit(2);
+        }
+    };
+    match opts.command {
+        Command::Play => (),
+        Command::Help => return println!("{}", cli::USAGE),
+        _ => process::exit(commands::run(&opts)),
+    }
+
+    let rules = commands::rules(&opts).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+
     let mut window: PistonWindow<Sdl2Window> =
-        WindowSettings::new("sokoban", (15 * 64, 11 * 64 - 6))
+        WindowSettings::new("sokoban", window_size(opts.size, opts.tile_size))
             .exit_on_esc(true)
             //.opengl(OpenGL::V3_2)
             .resizable(true)
             .build()
             .unwrap();
     window.hide();
-    let mut game = Game::new((size.0 as usize, size.1 as usize));
+    let mut game = Game::new(commands::new_state(&opts), &opts.assets, opts.limit);
+    game.state.rules = rules;
+    game.animation.duration = opts.move_time as f64 / 1000.;
+    if let Some(color) = game
+        .state
+        .colors
+        .iter()
+        .find(|c| !game.obj_tex._crate.contains_key(*c))
+    {
+        eprintln!("no crate texture for {}", color);
+        process::exit(1);
+    }
 
-    let bef_gen = SystemTime::now();
-    game.gen_level();
-    let time = SystemTime::now()
-        .duration_since(bef_gen)
-        .unwrap()
-        .subsec_nanos() as f64
-        / 1_000_000_000.;
-    println!("time generating map: {:?}", time);
+    if let Some(ref path) = opts.edit {
+        match Editor::open(path, opts.index.unwrap_or(0), opts.size) {
+            Ok(editor) => game.open_editor(editor),
+            Err(e) => {
+                eprintln!("could not load {}: {}", path, e);
+                process::exit(1);
+            }
+        }
+    } else if let Some(ref path) = opts.replay {
+        match Replay::load(path) {
+            Ok(replay) => game.open_replay(&replay),
+            Err(e) => {
+                eprintln!("could not load {}: {}", path, e);
+                process::exit(1);
+            }
+        }
+    } else if opts.file.is_some() {
+        match commands::open_file(&opts, &mut game.session, &mut game.state) {
+            Ok(message) => game.hint = message,
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    } else if let Some(seed) = opts.seed {
+        game.generate(Some(seed));
+    } else {
+        let bef_gen = SystemTime::now();
+        game.generate(None);
+        let time = SystemTime::now()
+            .duration_since(bef_gen)
+            .unwrap()
+            .subsec_nanos() as f64
+            / 1_000_000_000.;
+        println!("time generating map: {:?}", time);
+    }
+    window.set_size(window_size(game.state.dimensions, opts.tile_size));
     window.show();
     let arc_game = Arc::new(RwLock::new(game));
 
     while let Some(e) = window.next() {
         if let Some(Button::Keyboard(key)) = e.press_args() {
-            arc_game.write().unwrap().move_player(key);
+            arc_game.write().unwrap().press(key);
+        }
+        if let Some(Button::Mouse(button)) = e.press_args() {
+            arc_game.write().unwrap().mouse_press(button);
+        }
+        if let Some(Button::Keyboard(key)) = e.release_args() {
+            arc_game.write().unwrap().release(key);
+        }
+        if let Some(Button::Mouse(button)) = e.release_args() {
+            arc_game.write().unwrap().mouse_release(button);
+        }
+        if let Some(cursor) = e.mouse_cursor_args() {
+            arc_game.write().unwrap().move_cursor(cursor);
         }
         if let Some(r) = e.render_args() {
-            let factory = window.factory.clone();
             arc_game.write().unwrap().render(&r);
         }
+        if let Some(scroll) = e.mouse_scroll_args() {
+            arc_game.write().unwrap().camera.scroll(scroll[1]);
+        }
         if let Some(u) = e.update_args() {
             arc_game.write().unwrap().update(&u);
         }
@@ -0,0 +1,442 @@
+//! Level packs played in order, and the progress kept between launches.
+//!
+//! Progress lives in a tab separated text file under the user's data
+//! directory, one line per level that was played:
+//!
+//! ```text
+//! current <pack>  <index>
+//! level   <pack>  <index>  <solved|unsolved>  <moves>  <pushes>  <seconds>
+//! ```
+//!
+//! Packs are named by the full path of their file, numbers that aren't
+//! known yet are written as `-`.
+//!
+//! `Session` moves from level to level the same way in both front ends.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use level::{load_collection, Level, LevelError};
+use state::GameState;
+
+/// A collection of levels and the one being played.
+pub struct Pack {
+    /// Full path of the file, identifies the pack in the progress file.
+    pub name: String,
+    pub levels: Vec<Level>,
+    pub index: usize,
+}
+
+/// How a level went, keeping the best of every solve.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    pub solved: bool,
+    pub moves: Option<u32>,
+    pub pushes: Option<u32>,
+    /// Seconds.
+    pub time: Option<u64>,
+}
+
+pub struct Progress {
+    path: Option<PathBuf>,
+    current: BTreeMap<String, usize>,
+    records: BTreeMap<(String, usize), Record>,
+}
+
+/// What's being played, a pack or random levels, and the progress made.
+/// Methods that change level load it into the `GameState` passed, and
+/// return a message for the player if there's one.
+pub struct Session {
+    /// Pack being played, random levels are generated without one.
+    pub pack: Option<Pack>,
+    pub progress: Progress,
+    /// Set while "generate a new level?" waits for an answer.
+    confirm_generate: bool,
+}
+
+impl Pack {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Pack, LevelError> {
+        let path = path.as_ref();
+        let levels = load_collection(path)?;
+        if levels.is_empty() {
+            return Err(LevelError::Parse {
+                line: 1,
+                message: "no levels in the file".to_string(),
+            });
+        }
+        let name = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        Ok(Pack {
+            name: name.to_string_lossy().into_owned(),
+            levels: levels,
+            index: 0,
+        })
+    }
+
+    pub fn level(&self) -> &Level {
+        &self.levels[self.index]
+    }
+
+    /// Moves `by` levels forward, or back if negative, wrapping around.
+    pub fn advance(&mut self, by: isize) {
+        let n = self.levels.len() as isize;
+        self.index = (self.index as isize + by).rem_euclid(n) as usize;
+    }
+
+    /// Moves to the first unsolved level after the current one, wrapping
+    /// around. Returns false, staying put, once every level is solved.
+    pub fn next_unsolved(&mut self, progress: &Progress) -> bool {
+        let n = self.levels.len();
+        for i in 1..n + 1 {
+            let index = (self.index + i) % n;
+            if !progress.is_solved(&self.name, index) {
+                self.index = index;
+                return true;
+            }
+        }
+        false
+    }
+}
+
+impl Record {
+    fn unsolved() -> Record {
+        Record {
+            solved: false,
+            moves: None,
+            pushes: None,
+            time: None,
+        }
+    }
+}
+
+/// Where progress and other user data is kept, `$XDG_DATA_HOME/sokoban`,
+/// `~/.local/share/sokoban` or `%APPDATA%\sokoban`.
+pub fn data_dir() -> Option<PathBuf> {
+    let dir = |var: &str| {
+        env::var_os(var)
+            .filter(|d| !d.is_empty())
+            .map(PathBuf::from)
+    };
+    if let Some(dir) = dir("XDG_DATA_HOME") {
+        Some(dir.join("sokoban"))
+    } else if let Some(dir) = dir("APPDATA") {
+        Some(dir.join("sokoban"))
+    } else {
+        dir("HOME").map(|home| home.join(".local").join("share").join("sokoban"))
+    }
+}
+
+fn min(best: Option<u32>, new: Option<u32>) -> Option<u32> {
+    match (best, new) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+fn field<T: ToString>(value: Option<T>) -> String {
+    value
+        .map(|v| v.to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+impl Progress {
+    /// Progress that isn't saved anywhere.
+    pub fn new() -> Progress {
+        Progress {
+            path: None,
+            current: BTreeMap::new(),
+            records: BTreeMap::new(),
+        }
+    }
+
+    /// Progress from the file in `data_dir`, empty if there's none yet.
+    pub fn load_default() -> io::Result<Progress> {
+        match data_dir() {
+            Some(dir) => Progress::load(dir.join("progress.txt")),
+            None => Ok(Progress::new()),
+        }
+    }
+
+    /// Reads `path`, lines that can't be read are skipped.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Progress> {
+        let mut progress = Progress::new();
+        progress.path = Some(path.as_ref().to_path_buf());
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(progress),
+            Err(e) => return Err(e),
+        };
+        for line in text.lines() {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let index = match fields.get(2).and_then(|i| i.parse().ok()) {
+                Some(i) => i,
+                None => continue,
+            };
+            let pack = fields[1].to_string();
+            match (fields[0], fields.len()) {
+                ("current", 3) => {
+                    progress.current.insert(pack, index);
+                }
+                ("level", 7) => {
+                    let record = Record {
+                        solved: fields[3] == "solved",
+                        moves: fields[4].parse().ok(),
+                        pushes: fields[5].parse().ok(),
+                        time: fields[6].parse().ok(),
+                    };
+                    progress.records.insert((pack, index), record);
+                }
+                _ => (),
+            }
+        }
+        Ok(progress)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut text = String::new();
+        for (pack, index) in self.current.iter() {
+            text.push_str(&format!("current\t{}\t{}\n", pack, index));
+        }
+        for (&(ref pack, index), r) in self.records.iter() {
+            text.push_str(&format!(
+                "level\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                pack,
+                index,
+                if r.solved { "solved" } else { "unsolved" },
+                field(r.moves),
+                field(r.pushes),
+                field(r.time)
+            ));
+        }
+        fs::write(path, text)
+    }
+
+    /// Level of `pack` that was played last.
+    pub fn current(&self, pack: &str) -> Option<usize> {
+        self.current.get(pack).cloned()
+    }
+
+    pub fn record(&self, pack: &str, index: usize) -> Option<&Record> {
+        self.records.get(&(pack.to_string(), index))
+    }
+
+    pub fn is_solved(&self, pack: &str, index: usize) -> bool {
+        self.record(pack, index).is_some_and(|r| r.solved)
+    }
+
+    /// Notes that level `index` of `pack` is being played.
+    pub fn start(&mut self, pack: &str, index: usize) {
+        self.current.insert(pack.to_string(), index);
+        self.records
+            .entry((pack.to_string(), index))
+            .or_insert_with(Record::unsolved);
+    }
+
+    /// Notes a solve of level `index`, keeping the best numbers.
+    pub fn solve(&mut self, pack: &str, index: usize, moves: u32, pushes: u32, time: u64) {
+        let record = self
+            .records
+            .entry((pack.to_string(), index))
+            .or_insert_with(Record::unsolved);
+        record.solved = true;
+        record.moves = min(record.moves, Some(moves));
+        record.pushes = min(record.pushes, Some(pushes));
+        record.time = Some(record.time.map_or(time, |t| t.min(time)));
+    }
+}
+
+impl Default for Progress {
+    fn default() -> Progress {
+        Progress::new()
+    }
+}
+
+impl Session {
+    pub fn new(progress: Progress) -> Session {
+        Session {
+            pack: None,
+            progress: progress,
+            confirm_generate: false,
+        }
+    }
+
+    /// Plays `pack` from `index`, or the level left off last time.
+    pub fn open_pack(
+        &mut self,
+        mut pack: Pack,
+        index: Option<usize>,
+        state: &mut GameState,
+    ) -> Option<String> {
+        pack.index = index
+            .or_else(|| self.progress.current(&pack.name))
+            .filter(|i| *i < pack.levels.len())
+            .unwrap_or(0);
+        self.pack = Some(pack);
+        self.start_level(state)
+    }
+
+    /// Loads the current level of the pack and notes it's being played.
+    pub fn start_level(&mut self, state: &mut GameState) -> Option<String> {
+        let pack = self.pack.as_ref()?;
+        self.progress.start(&pack.name, pack.index);
+        state.load_level(pack.level());
+        self.progress
+            .save()
+            .err()
+            .map(|e| format!("Could not save progress: {}", e))
+    }
+
+    /// Records the solved level and moves on to the next unsolved one of
+    /// the pack, or to a new random level.
+    pub fn next_level(&mut self, state: &mut GameState) -> Option<String> {
+        let time = SystemTime::now()
+            .duration_since(state.start_t)
+            .map(|t| t.as_secs())
+            .unwrap_or(0);
+        let complete = match self.pack {
+            Some(ref mut pack) => {
+                let score = &state.score;
+                let (moves, pushes) = (score.moves as u32, score.pushes as u32);
+                self.progress
+                    .solve(&pack.name, pack.index, moves, pushes, time);
+                let complete = !pack.next_unsolved(&self.progress);
+                if complete {
+                    pack.advance(1);
+                }
+                complete
+            }
+            None => {
+                state.gen_level();
+                return None;
+            }
+        };
+        let saved = self.start_level(state);
+        if complete {
+            Some("Pack complete!".to_string())
+        } else {
+            saved
+        }
+    }
+
+    /// Plays the level `by` levels away in the pack.
+    pub fn skip_level(&mut self, by: isize, state: &mut GameState) -> Option<String> {
+        match self.pack {
+            Some(ref mut pack) => pack.advance(by),
+            None => return Some("No pack open".to_string()),
+        }
+        self.start_level(state)
+    }
+
+    /// Asks before throwing the level away for a new random one, the
+    /// answer goes to `answer_generate`.
+    pub fn ask_generate(&mut self) -> String {
+        self.confirm_generate = true;
+        "Generate a new level? Y/N".to_string()
+    }
+
+    /// Whether `ask_generate` is waiting for an answer.
+    pub fn asking(&self) -> bool {
+        self.confirm_generate
+    }
+
+    /// Leaves the pack for a new random level if the answer was `yes`.
+    /// Returns whether there's a new level.
+    pub fn answer_generate(&mut self, yes: bool, state: &mut GameState) -> bool {
+        self.confirm_generate = false;
+        if yes {
+            self.pack = None;
+            state.gen_level();
+        }
+        yes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    const PACK: &str = "#####\n#@$.#\n#####\n\n#####\n#.$@#\n#####\n\n######\n#@$ .#\n######\n";
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn progress_keeps_the_best_solve() {
+        let dir = temp_dir("sokoban-pack-progress");
+        let path = dir.join("progress.txt");
+        let mut progress = Progress::load(&path).unwrap();
+        progress.start("pack", 0);
+        progress.solve("pack", 0, 10, 4, 5);
+        progress.solve("pack", 0, 12, 3, 4);
+        progress.start("pack", 1);
+        progress.save().unwrap();
+        let progress = Progress::load(&path).unwrap();
+        assert_eq!(progress.current("pack"), Some(1));
+        let record = progress.record("pack", 0).unwrap();
+        assert_eq!(
+            (record.solved, record.moves, record.pushes, record.time),
+            (true, Some(10), Some(3), Some(4))
+        );
+        assert!(!progress.is_solved("pack", 1));
+    }
+
+    #[test]
+    fn next_unsolved_skips_solved_levels() {
+        let dir = temp_dir("sokoban-pack-next");
+        let file = dir.join("pack.txt");
+        fs::write(&file, PACK).unwrap();
+        let mut pack = Pack::open(&file).unwrap();
+        assert_eq!(pack.levels.len(), 3);
+        let mut progress = Progress::new();
+        progress.solve(&pack.name, 1, 1, 1, 1);
+        assert!(pack.next_unsolved(&progress));
+        assert_eq!(pack.index, 2);
+        progress.solve(&pack.name, 2, 2, 1, 1);
+        progress.solve(&pack.name, 0, 1, 1, 1);
+        assert!(!pack.next_unsolved(&progress));
+    }
+
+    #[test]
+    fn advance_wraps_around() {
+        let dir = temp_dir("sokoban-pack-advance");
+        let file = dir.join("pack.txt");
+        fs::write(&file, PACK).unwrap();
+        let mut pack = Pack::open(&file).unwrap();
+        pack.advance(-1);
+        assert_eq!(pack.index, 2);
+        pack.advance(1);
+        assert_eq!(pack.index, 0);
+    }
+
+    #[test]
+    fn sessions_move_on_to_the_next_unsolved_level() {
+        let dir = temp_dir("sokoban-pack-session");
+        let file = dir.join("pack.txt");
+        fs::write(&file, PACK).unwrap();
+        let mut state = GameState::new((15, 10), Vec::new());
+        let mut session = Session::new(Progress::new());
+        session.open_pack(Pack::open(&file).unwrap(), Some(1), &mut state);
+        assert_eq!(session.next_level(&mut state), None);
+        assert_eq!(session.pack.as_ref().unwrap().index, 2);
+        assert!(session
+            .progress
+            .is_solved(&session.pack.as_ref().unwrap().name, 1));
+        assert!(session.skip_level(1, &mut state).is_none());
+        assert_eq!(session.pack.as_ref().unwrap().index, 0);
+    }
+}
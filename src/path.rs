@@ -0,0 +1,243 @@
+//! Routes for moving with the mouse: walking to a cell, and taking a crate
+//! to a cell with the walks and pushes in between. Routes are key presses,
+//! played through `GameState::move_player` like any other move. They keep
+//! off special tiles that would move the player or the crate on their own.
+
+use std::collections::{HashMap, VecDeque};
+
+use state::{Direction, GameState, ObjectType, RuleSet};
+use structs::Position;
+use tiles::{self, Behavior};
+
+const DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Down,
+    Direction::Left,
+    Direction::Right,
+];
+
+/// The board as cells, `width` per row.
+struct Grid {
+    width: usize,
+    height: usize,
+    /// Walls, the outside and every crate.
+    blocked: Vec<bool>,
+    /// Color of the free target on each cell.
+    target: Vec<Option<String>>,
+    /// Crates pass over targets and don't lock.
+    classic: bool,
+    /// Special tile on each cell.
+    tiles: Vec<Option<&'static dyn Behavior>>,
+}
+
+impl Grid {
+    fn new(state: &GameState) -> Grid {
+        let (width, height) = state.dimensions;
+        let mut grid = Grid {
+            width: width,
+            height: height,
+            blocked: vec![true; width * height],
+            target: vec![None; width * height],
+            classic: state.rule_set == RuleSet::Classic,
+            tiles: vec![None; width * height],
+        };
+        for obj in state.floor.iter() {
+            let cell = grid.index(&obj.position);
+            grid.blocked[cell] = false;
+            grid.tiles[cell] = tiles::of(obj);
+        }
+        for obj in state.special.iter() {
+            let cell = grid.index(&obj.position);
+            match obj.obj_type {
+                ObjectType::Target => grid.target[cell] = obj.sprite.clone(),
+                ObjectType::Passing => (),
+                _ => grid.blocked[cell] = true,
+            }
+        }
+        grid
+    }
+
+    fn index(&self, pos: &Position) -> usize {
+        pos.get_y() as usize * self.width + pos.get_x() as usize
+    }
+
+    fn cell(&self, pos: &Position) -> Option<usize> {
+        if pos.is_within((self.width, self.height)) {
+            Some(self.index(pos))
+        } else {
+            None
+        }
+    }
+
+    /// Whether the tile at `cell` lets the player step on heading `dir`
+    /// and stay there.
+    fn player_fits(&self, cell: usize, dir: Direction) -> bool {
+        self.tiles[cell].map_or(true, |t| t.lets_player(dir) && !t.acts_on_player())
+    }
+
+    /// Whether the tile at `cell` lets a crate be pushed on heading `dir`
+    /// and stay there.
+    fn crate_fits(&self, cell: usize, dir: Direction) -> bool {
+        self.tiles[cell].map_or(true, |t| t.lets_crate(dir) && !t.acts_on_crate())
+    }
+
+    fn step(&self, cell: usize, dir: Direction) -> Option<usize> {
+        let (x, y) = (cell % self.width, cell / self.width);
+        match dir {
+            Direction::Up if y > 0 => Some(cell - self.width),
+            Direction::Down if y + 1 < self.height => Some(cell + self.width),
+            Direction::Left if x > 0 => Some(cell - 1),
+            Direction::Right if x + 1 < self.width => Some(cell + 1),
+            _ => None,
+        }
+    }
+}
+
+/// Follows `came` back from `end` to the start of a search.
+fn route<K: Copy + Eq + ::std::hash::Hash>(
+    came: &HashMap<K, (K, Direction)>,
+    start: K,
+    end: K,
+) -> Vec<Direction> {
+    let mut moves = Vec::new();
+    let mut at = end;
+    while at != start {
+        let (prev, dir) = came[&at];
+        moves.push(dir);
+        at = prev;
+    }
+    moves.reverse();
+    moves
+}
+
+/// The shortest walk from the player to `to` around walls and crates, or
+/// None if it can't be reached.
+pub fn walk(state: &GameState, to: Position) -> Option<Vec<Direction>> {
+    let grid = Grid::new(state);
+    let start = grid.cell(&state.player.position)?;
+    let end = grid.cell(&to)?;
+    let mut came = HashMap::new();
+    let mut queue = VecDeque::new();
+    queue.push_back(start);
+    while let Some(cell) = queue.pop_front() {
+        if cell == end {
+            return Some(route(&came, start, end));
+        }
+        for dir in DIRECTIONS.iter() {
+            if let Some(next) = grid.step(cell, *dir) {
+                if !grid.blocked[next]
+                    && grid.player_fits(next, *dir)
+                    && next != start
+                    && !came.contains_key(&next)
+                {
+                    came.insert(next, (cell, *dir));
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// The fewest moves taking the crate at `from` to `to`, walking and
+/// pushing, with every other crate left where it is. None if there's no
+/// loose crate at `from` or no way to get it there. Unless playing by
+/// classic rules the crate may only end on a target of its own color,
+/// where it locks.
+pub fn push_to(state: &GameState, from: Position, to: Position) -> Option<Vec<Direction>> {
+    let color = state
+        .special
+        .iter()
+        .find(|o| o.position == from && o.obj_type == ObjectType::Crate)?
+        .sprite
+        .clone();
+    let mut grid = Grid::new(state);
+    let from = grid.cell(&from)?;
+    let to = grid.cell(&to)?;
+    grid.blocked[from] = false;
+    match grid.target[to] {
+        Some(ref target) if Some(target) != color.as_ref() && !grid.classic => return None,
+        _ if grid.blocked[to] => return None,
+        _ => (),
+    }
+
+    // states are the cells of the crate and the player
+    let start = (from, grid.cell(&state.player.position)?);
+    let mut came = HashMap::new();
+    let mut queue = VecDeque::new();
+    queue.push_back(start);
+    while let Some(node) = queue.pop_front() {
+        let (crate_cell, player) = node;
+        if crate_cell == to {
+            return Some(route(&came, start, node));
+        }
+        for dir in DIRECTIONS.iter() {
+            let next = match grid.step(player, *dir) {
+                Some(next) if !grid.blocked[next] && grid.player_fits(next, *dir) => next,
+                _ => continue,
+            };
+            let node = if next == crate_cell {
+                match grid.step(crate_cell, *dir) {
+                    Some(beyond) if !grid.blocked[beyond] && grid.crate_fits(beyond, *dir) => {
+                        // other targets would lock the crate or stop it
+                        if grid.target[beyond].is_some() && beyond != to && !grid.classic {
+                            continue;
+                        }
+                        (beyond, next)
+                    }
+                    _ => continue,
+                }
+            } else {
+                (crate_cell, next)
+            };
+            if node != start && !came.contains_key(&node) {
+                came.insert(node, ((crate_cell, player), *dir));
+                queue.push_back(node);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use level::Level;
+    use state::Move;
+
+    fn load(text: &str) -> GameState {
+        let mut state = GameState::new((15, 10), Vec::new());
+        state.load_level(&Level::parse(text).unwrap());
+        state
+    }
+
+    const ROOM: &str = "########\n#      #\n#@     #\n# $ #  #\n#   # .#\n########\n";
+
+    #[test]
+    fn walk_goes_around_walls_and_crates() {
+        let state = load(ROOM);
+        assert_eq!(walk(&state, Position::new(6, 4)).unwrap().len(), 7);
+        assert_eq!(walk(&state, Position::new(1, 2)), Some(Vec::new()));
+        assert!(walk(&state, Position::new(4, 3)).is_none());
+        assert!(walk(&state, Position::new(2, 3)).is_none());
+    }
+
+    #[test]
+    fn push_to_brings_a_crate_where_it_is_dropped() {
+        let mut state = load(ROOM);
+        let moves = push_to(&state, Position::new(2, 3), Position::new(6, 4)).unwrap();
+        for dir in moves {
+            assert_ne!(state.move_player(dir), Move::Blocked);
+        }
+        assert!(state.is_solved());
+    }
+
+    #[test]
+    fn push_to_gives_up_on_walls_and_stuck_crates() {
+        let state = load(ROOM);
+        assert!(push_to(&state, Position::new(2, 3), Position::new(4, 3)).is_none());
+        assert!(push_to(&state, Position::new(3, 4), Position::new(1, 1)).is_none());
+        let moves = push_to(&state, Position::new(2, 3), Position::new(2, 4)).unwrap();
+        assert_eq!(moves.last(), Some(&Direction::Down));
+    }
+}
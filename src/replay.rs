@@ -0,0 +1,290 @@
+//! Replays in LURD notation.
+//!
+//! Every step is one letter, `l` `u` `r` `d` for walking and `L` `U` `R`
+//! `D` for pushing. A number before a letter repeats it (`3r` is `rrr`),
+//! as some programs write solutions that way. Replays are saved as the
+//! level they were played on with the moves in a `Solution:` line:
+//!
+//! ```text
+//! #####
+//! #@$.#
+//! #####
+//! Solution: R
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+use level::{load_collection, save_collection, Level, LevelError};
+use state::{lurd_char, Direction, GameState, Move, Score};
+
+/// One step of a replay.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Step {
+    pub direction: Direction,
+    pub push: bool,
+}
+
+/// A level and the moves played on it.
+#[derive(Clone, Debug)]
+pub struct Replay {
+    pub level: Level,
+    pub steps: Vec<Step>,
+    /// Counters of the recorded game, saved as a comment with the moves.
+    pub score: Option<Score>,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Level(LevelError),
+    /// A character that isn't a move, at that index of the text.
+    Parse {
+        index: usize,
+        found: char,
+    },
+    /// Step `step` (from 0) doesn't do what the replay says.
+    Mismatch {
+        step: usize,
+        expected: Step,
+        found: Move,
+    },
+    NoSolution,
+}
+
+/// How a checked replay ended.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Verdict {
+    Solved,
+    /// Every step could be played but some target is still free.
+    Unsolved,
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReplayError::Level(ref e) => write!(f, "{}", e),
+            ReplayError::Parse { index, found } => {
+                write!(f, "unexpected '{}' at {} in the moves", found, index)
+            }
+            ReplayError::Mismatch {
+                step,
+                expected,
+                found,
+            } => write!(
+                f,
+                "move {} ('{}') can't be played, it's {:?}",
+                step + 1,
+                lurd_char(expected.direction, expected.push),
+                found
+            ),
+            ReplayError::NoSolution => write!(f, "no Solution line in the level"),
+        }
+    }
+}
+
+impl Error for ReplayError {}
+
+impl From<LevelError> for ReplayError {
+    fn from(e: LevelError) -> ReplayError {
+        ReplayError::Level(e)
+    }
+}
+
+/// The `Solution:` line, or the counters written after it.
+fn is_solution(note: &str) -> bool {
+    note.to_ascii_lowercase().starts_with("solution:")
+        || (note.starts_with("; ") && note.ends_with(" box changes"))
+}
+
+pub fn parse_lurd(text: &str) -> Result<Vec<Step>, ReplayError> {
+    let mut steps = Vec::new();
+    let mut count: Option<usize> = None;
+    for (index, c) in text.char_indices() {
+        if let Some(digit) = c.to_digit(10) {
+            count = Some(count.unwrap_or(0) * 10 + digit as usize);
+            continue;
+        }
+        if c.is_whitespace() {
+            continue;
+        }
+        let direction = match c.to_ascii_lowercase() {
+            'l' => Direction::Left,
+            'u' => Direction::Up,
+            'r' => Direction::Right,
+            'd' => Direction::Down,
+            _ => {
+                return Err(ReplayError::Parse {
+                    index: index,
+                    found: c,
+                })
+            }
+        };
+        let step = Step {
+            direction: direction,
+            push: c.is_ascii_uppercase(),
+        };
+        for _ in 0..count.take().unwrap_or(1) {
+            steps.push(step);
+        }
+    }
+    Ok(steps)
+}
+
+pub fn to_lurd(steps: &[Step]) -> String {
+    steps
+        .iter()
+        .map(|s| lurd_char(s.direction, s.push))
+        .collect()
+}
+
+impl Replay {
+    /// The moves made on `state` since its level was loaded.
+    pub fn record(state: &GameState) -> Option<Replay> {
+        let level = state.level.clone()?;
+        Some(Replay {
+            level: level,
+            steps: parse_lurd(state.lurd()).unwrap(),
+            score: Some(state.score.clone()),
+        })
+    }
+
+    /// The replay stored in a level's `Solution:` line.
+    pub fn from_level(level: &Level) -> Result<Replay, ReplayError> {
+        let lurd = level.meta("Solution").ok_or(ReplayError::NoSolution)?;
+        let steps = parse_lurd(lurd)?;
+        let mut level = level.clone();
+        level.notes.retain(|n| !is_solution(n));
+        Ok(Replay {
+            level: level,
+            steps: steps,
+            score: None,
+        })
+    }
+
+    pub fn to_level(&self) -> Level {
+        let mut level = self.level.clone();
+        level.notes.retain(|n| !is_solution(n));
+        level
+            .notes
+            .push(format!("Solution: {}", to_lurd(&self.steps)));
+        if let Some(ref score) = self.score {
+            level.notes.push(format!("; {}", score));
+        }
+        level
+    }
+
+    /// Reads the first level of `path` that has a solution.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Replay, ReplayError> {
+        load_collection(path)?
+            .iter()
+            .filter(|l| l.meta("Solution").is_some())
+            .map(Replay::from_level)
+            .next()
+            .unwrap_or(Err(ReplayError::NoSolution))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ReplayError> {
+        save_collection(path, &[self.to_level()])?;
+        Ok(())
+    }
+
+    /// Plays the replay from the start of its level on `state`, stopping
+    /// at the first step that doesn't walk or push as written.
+    pub fn verify(&self, state: &mut GameState) -> Result<Verdict, ReplayError> {
+        state.load_level(&self.level);
+        for (i, step) in self.steps.iter().enumerate() {
+            let found = state.move_player(step.direction);
+            let expected = if step.push { Move::Push } else { Move::Walk };
+            if found != expected {
+                return Err(ReplayError::Mismatch {
+                    step: i,
+                    expected: *step,
+                    found: found,
+                });
+            }
+        }
+        if state.is_solved() {
+            Ok(Verdict::Solved)
+        } else {
+            Ok(Verdict::Unsolved)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEVEL: &str = "#######\n#@ $ .#\n#######\n";
+
+    fn replay(moves: &str) -> Replay {
+        Replay {
+            level: Level::parse(LEVEL).unwrap(),
+            steps: parse_lurd(moves).unwrap(),
+            score: None,
+        }
+    }
+
+    #[test]
+    fn counts_repeat_the_next_step() {
+        let steps = parse_lurd("3rU d\n12L").unwrap();
+        assert_eq!(steps.len(), 17);
+        assert_eq!(to_lurd(&steps), format!("rrrUd{}", "L".repeat(12)));
+        assert!(steps[3].push && !steps[4].push);
+    }
+
+    #[test]
+    fn unknown_letters_are_errors() {
+        match parse_lurd("rr2x") {
+            Err(ReplayError::Parse { index, found }) => assert_eq!((index, found), (3, 'x')),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn undone_moves_are_left_out_of_the_record() {
+        let mut state = GameState::new((15, 10), Vec::new());
+        state.load_level(&Level::parse(LEVEL).unwrap());
+        state.move_player(Direction::Right);
+        state.move_player(Direction::Right);
+        state.undo();
+        state.move_player(Direction::Left);
+        state.move_player(Direction::Right);
+        state.move_player(Direction::Right);
+        state.undo();
+        state.redo();
+        assert_eq!(state.lurd(), "rlrR");
+        state.move_player(Direction::Right);
+        let recorded = Replay::record(&state).unwrap();
+        let loaded = Replay::from_level(&recorded.to_level()).unwrap();
+        assert_eq!(to_lurd(&loaded.steps), "rlrRR");
+        assert_eq!(loaded.verify(&mut state).unwrap(), Verdict::Solved);
+    }
+
+    #[test]
+    fn counters_are_saved_next_to_the_moves() {
+        let mut state = GameState::new((15, 10), Vec::new());
+        state.load_level(&Level::parse(LEVEL).unwrap());
+        state.move_player(Direction::Right);
+        state.move_player(Direction::Right);
+        let level = Replay::record(&state).unwrap().to_level();
+        assert!(level
+            .notes
+            .contains(&"; 2 moves, 1 pushes, 1 box lines, 1 box changes".to_string()));
+        let loaded = Replay::from_level(&level).unwrap();
+        assert_eq!(to_lurd(&loaded.steps), "rR");
+    }
+
+    #[test]
+    fn verify_stops_at_the_first_wrong_step() {
+        let mut state = GameState::new((15, 10), Vec::new());
+        assert_eq!(replay("rR").verify(&mut state).unwrap(), Verdict::Unsolved);
+        match replay("rrR").verify(&mut state) {
+            Err(ReplayError::Mismatch { step, found, .. }) => {
+                assert_eq!((step, found), (1, Move::Push))
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+}
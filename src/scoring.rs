@@ -0,0 +1,187 @@
+//! How the score is counted, read from a text file of `key = value` lines.
+//! Every key is optional, `#` starts a comment:
+//!
+//! ```text
+//! crate = 100        # points per crate locked on its target
+//! move = 1           # taken off per move
+//! push = 0           # taken off per push, on top of the move
+//! second = 1         # taken off per second played
+//! par moves = 50     # bonus for a solve within the level's par moves
+//! par pushes = 50    # bonus for a solve within the level's par pushes
+//! color red = 2      # red crates are worth twice the points
+//! ```
+//!
+//! The par of a level is its `Par:` line, moves then pushes, e.g.
+//! `Par: 40 12`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use level::Level;
+use pack::data_dir;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScoringRules {
+    /// Points for each crate locked on its target.
+    pub crate_points: i32,
+    pub move_penalty: i32,
+    pub push_penalty: i32,
+    /// Taken off per second.
+    pub time_penalty: i32,
+    pub par_moves_bonus: i32,
+    pub par_pushes_bonus: i32,
+    /// Crate points are multiplied by these, keyed by color like "red.png".
+    /// Colors not listed count once.
+    pub multipliers: BTreeMap<String, f64>,
+}
+
+impl Default for ScoringRules {
+    fn default() -> ScoringRules {
+        ScoringRules {
+            crate_points: 100,
+            move_penalty: 1,
+            push_penalty: 0,
+            time_penalty: 1,
+            par_moves_bonus: 0,
+            par_pushes_bonus: 0,
+            multipliers: BTreeMap::new(),
+        }
+    }
+}
+
+fn invalid(line: usize, message: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("line {}: {}", line, message),
+    )
+}
+
+/// Moves and pushes of the `Par:` line of `level`.
+pub fn par(level: &Level) -> (Option<u32>, Option<u32>) {
+    let mut numbers = level
+        .meta("Par")
+        .unwrap_or("")
+        .split_whitespace()
+        .map(|n| n.parse().ok());
+    (
+        numbers.next().and_then(|n| n),
+        numbers.next().and_then(|n| n),
+    )
+}
+
+impl ScoringRules {
+    /// Rules from `scoring.txt` in `data_dir`, the defaults if there's none.
+    pub fn load_default() -> io::Result<ScoringRules> {
+        match data_dir() {
+            Some(dir) => match ScoringRules::load(dir.join("scoring.txt")) {
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(ScoringRules::default()),
+                result => result,
+            },
+            None => Ok(ScoringRules::default()),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<ScoringRules> {
+        ScoringRules::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> io::Result<ScoringRules> {
+        let mut rules = ScoringRules::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = match line.find('#') {
+                Some(end) => &line[..end],
+                None => line,
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (key, value) = match line.find('=') {
+                Some(eq) => (line[..eq].trim(), line[eq + 1..].trim()),
+                None => return Err(invalid(i + 1, "expected key = value".to_string())),
+            };
+            let key: Vec<&str> = key.split_whitespace().collect();
+            if key.len() == 2 && key[0] == "color" {
+                let color = if key[1].contains('.') {
+                    key[1].to_string()
+                } else {
+                    format!("{}.png", key[1])
+                };
+                let multiplier = value
+                    .parse()
+                    .map_err(|_| invalid(i + 1, format!("'{}' isn't a number", value)))?;
+                rules.multipliers.insert(color, multiplier);
+                continue;
+            }
+            let field = match key.join(" ").as_str() {
+                "crate" => &mut rules.crate_points,
+                "move" => &mut rules.move_penalty,
+                "push" => &mut rules.push_penalty,
+                "second" => &mut rules.time_penalty,
+                "par moves" => &mut rules.par_moves_bonus,
+                "par pushes" => &mut rules.par_pushes_bonus,
+                key => return Err(invalid(i + 1, format!("unknown key '{}'", key))),
+            };
+            *field = value
+                .parse()
+                .map_err(|_| invalid(i + 1, format!("'{}' isn't a whole number", value)))?;
+        }
+        Ok(rules)
+    }
+
+    /// Points for locking a crate of `color`.
+    pub fn crate_value(&self, color: &str) -> i32 {
+        let multiplier = self.multipliers.get(color).cloned().unwrap_or(1.);
+        (self.crate_points as f64 * multiplier).round() as i32
+    }
+
+    /// Bonus for solving `level` in `moves` and `pushes`.
+    pub fn par_bonus(&self, level: &Level, moves: i32, pushes: i32) -> i32 {
+        let (par_moves, par_pushes) = par(level);
+        let mut bonus = 0;
+        if par_moves.is_some_and(|par| moves <= par as i32) {
+            bonus += self.par_moves_bonus;
+        }
+        if par_pushes.is_some_and(|par| pushes <= par as i32) {
+            bonus += self.par_pushes_bonus;
+        }
+        bonus
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_reads_keys_colors_and_comments() {
+        let text = "# rules\ncrate = 10\nmove = 2 # each\npar moves = 7\ncolor red = 3\n\n";
+        let rules = ScoringRules::parse(text).unwrap();
+        assert_eq!(rules.crate_points, 10);
+        assert_eq!(rules.move_penalty, 2);
+        assert_eq!(rules.par_moves_bonus, 7);
+        assert_eq!(rules.time_penalty, ScoringRules::default().time_penalty);
+        assert_eq!(rules.crate_value("red.png"), 30);
+        assert_eq!(rules.crate_value("blue.png"), 10);
+    }
+
+    #[test]
+    fn parse_rejects_unknown_keys_and_values() {
+        assert!(ScoringRules::parse("bogus = 1").is_err());
+        assert!(ScoringRules::parse("crate = x").is_err());
+        assert!(ScoringRules::parse("crate").is_err());
+        assert!(ScoringRules::parse("color red = many").is_err());
+    }
+
+    #[test]
+    fn par_bonus_needs_the_par_line() {
+        let rules = ScoringRules::parse("par moves = 7\npar pushes = 3").unwrap();
+        let level = Level::parse("#####\n#@$.#\n#####\nPar: 1 1\n").unwrap();
+        assert_eq!(par(&level), (Some(1), Some(1)));
+        assert_eq!(rules.par_bonus(&level, 1, 1), 10);
+        assert_eq!(rules.par_bonus(&level, 2, 1), 3);
+        let plain = Level::parse("#####\n#@$.#\n#####\n").unwrap();
+        assert_eq!(rules.par_bonus(&plain, 1, 1), 0);
+    }
+}
@@ -0,0 +1,555 @@
+//! A* search over pushes.
+//!
+//! States are the positions of the crates still loose, which targets are
+//! already taken and the area the player can walk in. The lower bound
+//! pairs every free target with a distinct crate of its color (a minimum
+//! cost matching) using push distances on the empty board, so the first
+//! solution found has the fewest pushes.
+//!
+//! Under classic rules crates never lock, so no target is ever taken and
+//! the search ends once every target has a crate of its color on it.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+
+use state::{Direction, GameState, ObjectType, RuleSet};
+use structs::Position;
+
+pub(crate) const INF: u32 = u32::MAX;
+
+const DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Down,
+    Direction::Left,
+    Direction::Right,
+];
+
+/// Crates above this many per color make the bound fall back to the
+/// closest crate for each target.
+pub(crate) const MATCHING_LIMIT: usize = 12;
+
+/// A crate at `position` pushed one cell towards `direction`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Push {
+    pub position: Position,
+    pub direction: Direction,
+}
+
+#[derive(Clone, Debug)]
+pub struct Solution {
+    pub pushes: Vec<Push>,
+    /// Every key press, walking included.
+    pub moves: Vec<Direction>,
+}
+
+/// Why `solve` came back without a solution.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SolveError {
+    /// The search ran out of positions or hit the limit.
+    NotFound,
+    /// The board has special tiles, which the solver doesn't handle.
+    SpecialTiles,
+    /// More free targets than the search can keep track of.
+    TooManyTargets,
+}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SolveError::NotFound => write!(f, "no solution found"),
+            SolveError::SpecialTiles => write!(f, "the solver doesn't handle special tiles"),
+            SolveError::TooManyTargets => write!(f, "the solver handles up to 64 targets"),
+        }
+    }
+}
+
+impl Error for SolveError {}
+
+/// The parts of a level that never move.
+pub(crate) struct Board {
+    width: usize,
+    height: usize,
+    /// Walls, the outside and crates locked before the search started.
+    pub(crate) wall: Vec<bool>,
+    /// Cell and color of every free target.
+    pub(crate) targets: Vec<(usize, u8)>,
+    target_at: Vec<Option<usize>>,
+    /// Push distance from every cell to each target.
+    pub(crate) dist: Vec<Vec<u32>>,
+    /// Crates stay loose on their targets.
+    classic: bool,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Node {
+    /// Loose crates and their color, sorted.
+    crates: Vec<(usize, u8)>,
+    /// Targets already taken, one bit each.
+    done: u64,
+    /// Top-left-most cell the player can reach.
+    player: usize,
+}
+
+impl Board {
+    pub(crate) fn new(state: &GameState) -> (Board, Vec<(usize, u8)>, usize) {
+        let (width, height) = state.dimensions;
+        let index = |p: &Position| p.get_y() as usize * width + p.get_x() as usize;
+
+        let mut colors: Vec<String> = Vec::new();
+        let mut color_id = |name: &Option<String>| {
+            let name = name.clone().unwrap_or_default();
+            match colors.iter().position(|c| *c == name) {
+                Some(i) => i as u8,
+                None => {
+                    colors.push(name);
+                    (colors.len() - 1) as u8
+                }
+            }
+        };
+
+        let mut wall = vec![true; width * height];
+        for obj in state.floor.iter() {
+            wall[index(&obj.position)] = false;
+        }
+        let mut targets = Vec::new();
+        let mut crates = Vec::new();
+        for obj in state.special.iter() {
+            let cell = index(&obj.position);
+            match obj.obj_type {
+                ObjectType::Blocking => wall[cell] = true,
+                ObjectType::Crate => crates.push((cell, color_id(&obj.sprite))),
+                ObjectType::Target => targets.push((cell, color_id(&obj.sprite))),
+                ObjectType::Passing => (),
+            }
+        }
+        crates.sort();
+
+        let mut target_at = vec![None; width * height];
+        for (i, t) in targets.iter().enumerate() {
+            target_at[t.0] = Some(i);
+        }
+        let mut board = Board {
+            width: width,
+            height: height,
+            wall: wall,
+            targets: targets,
+            target_at: target_at,
+            dist: Vec::new(),
+            classic: state.rule_set == RuleSet::Classic,
+        };
+        board.dist = board
+            .targets
+            .iter()
+            .map(|t| board.push_distances(t.0))
+            .collect();
+        let player = index(&state.player.position);
+        (board, crates, player)
+    }
+
+    pub(crate) fn step(&self, cell: usize, dir: Direction) -> Option<usize> {
+        let (x, y) = (cell % self.width, cell / self.width);
+        match dir {
+            Direction::Up if y > 0 => Some(cell - self.width),
+            Direction::Down if y + 1 < self.height => Some(cell + self.width),
+            Direction::Left if x > 0 => Some(cell - 1),
+            Direction::Right if x + 1 < self.width => Some(cell + 1),
+            _ => None,
+        }
+    }
+
+    fn back(&self, cell: usize, dir: Direction) -> Option<usize> {
+        let opposite = match dir {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        };
+        self.step(cell, opposite)
+    }
+
+    fn open(&self, cell: Option<usize>) -> Option<usize> {
+        cell.and_then(|c| if self.wall[c] { None } else { Some(c) })
+    }
+
+    /// Pushes needed to bring a crate from each cell to `target` with no
+    /// other crate around.
+    fn push_distances(&self, target: usize) -> Vec<u32> {
+        let mut dist = vec![INF; self.wall.len()];
+        let mut queue = VecDeque::new();
+        dist[target] = 0;
+        queue.push_back(target);
+        while let Some(cell) = queue.pop_front() {
+            for dir in DIRECTIONS.iter() {
+                // the crate came from `from`, pushed by a player standing behind it
+                let from = match self.open(self.back(cell, *dir)) {
+                    Some(c) => c,
+                    None => continue,
+                };
+                if self.open(self.back(from, *dir)).is_none() || dist[from] != INF {
+                    continue;
+                }
+                dist[from] = dist[cell] + 1;
+                queue.push_back(from);
+            }
+        }
+        dist
+    }
+
+    fn blocked(&self, cell: usize, node: &Node) -> bool {
+        self.wall[cell]
+            || node.crates.iter().any(|c| c.0 == cell)
+            || match self.target_at[cell] {
+                Some(t) => node.done & (1 << t) != 0,
+                None => false,
+            }
+    }
+
+    /// Cells the player can walk to from `from`, and the walk to each.
+    fn reach(&self, from: usize, node: &Node) -> Vec<Option<(usize, Direction)>> {
+        let mut came = vec![None; self.wall.len()];
+        let mut seen = vec![false; self.wall.len()];
+        let mut queue = VecDeque::new();
+        seen[from] = true;
+        queue.push_back(from);
+        while let Some(cell) = queue.pop_front() {
+            for dir in DIRECTIONS.iter() {
+                if let Some(next) = self.step(cell, *dir) {
+                    if !seen[next] && !self.blocked(next, node) {
+                        seen[next] = true;
+                        came[next] = Some((cell, *dir));
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        came[from] = Some((from, Direction::Up));
+        came
+    }
+
+    fn normalize(&self, from: usize, node: &Node) -> usize {
+        self.reach(from, node)
+            .iter()
+            .position(|c| c.is_some())
+            .unwrap_or(from)
+    }
+
+    /// Lower bound of the pushes left, `INF` once some target can't be
+    /// filled anymore.
+    fn bound(&self, node: &Node) -> u32 {
+        let mut total = 0;
+        let mut colors: Vec<u8> = self.targets.iter().map(|t| t.1).collect();
+        colors.sort();
+        colors.dedup();
+        for color in colors {
+            let targets: Vec<usize> = (0..self.targets.len())
+                .filter(|t| self.targets[*t].1 == color && node.done & (1 << t) == 0)
+                .collect();
+            let crates: Vec<usize> = node
+                .crates
+                .iter()
+                .filter(|c| c.1 == color)
+                .map(|c| c.0)
+                .collect();
+            if targets.is_empty() {
+                continue;
+            }
+            if crates.len() < targets.len() {
+                return INF;
+            }
+            let cost = if crates.len() <= MATCHING_LIMIT {
+                self.matching(&targets, &crates)
+            } else {
+                let mut sum = 0;
+                for t in targets.iter() {
+                    let best = crates.iter().map(|c| self.dist[*t][*c]).min().unwrap();
+                    if best == INF {
+                        return INF;
+                    }
+                    sum += best;
+                }
+                sum
+            };
+            if cost == INF {
+                return INF;
+            }
+            total += cost;
+        }
+        total
+    }
+
+    /// Cheapest way to give each target its own crate.
+    pub(crate) fn matching(&self, targets: &[usize], crates: &[usize]) -> u32 {
+        let mut best = vec![INF; 1 << crates.len()];
+        best[0] = 0;
+        for (n, t) in targets.iter().enumerate() {
+            let mut next = vec![INF; 1 << crates.len()];
+            for (mask, cost) in best.iter().enumerate() {
+                if *cost == INF || (mask as u32).count_ones() as usize != n {
+                    continue;
+                }
+                for (i, c) in crates.iter().enumerate() {
+                    let d = self.dist[*t][*c];
+                    if mask & (1 << i) != 0 || d == INF {
+                        continue;
+                    }
+                    let m = mask | (1 << i);
+                    next[m] = next[m].min(cost + d);
+                }
+            }
+            best = next;
+        }
+        best.into_iter().min().unwrap_or(INF)
+    }
+
+    /// Every push the player can make from `node`.
+    fn pushes(&self, node: &Node) -> Vec<(Node, usize, Direction)> {
+        let reach = self.reach(node.player, node);
+        let mut out = Vec::new();
+        for (i, &(cell, color)) in node.crates.iter().enumerate() {
+            for dir in DIRECTIONS.iter() {
+                match self.back(cell, *dir) {
+                    Some(p) if reach[p].is_some() => (),
+                    _ => continue,
+                }
+                let to = match self.step(cell, *dir) {
+                    Some(t) if !self.blocked(t, node) => t,
+                    _ => continue,
+                };
+                let mut next = node.clone();
+                match self.target_at[to] {
+                    Some(t) if self.targets[t].1 != color && !self.classic => continue,
+                    Some(t) if !self.classic => {
+                        next.done |= 1 << t;
+                        next.crates.remove(i);
+                    }
+                    _ => {
+                        next.crates[i].0 = to;
+                        next.crates.sort();
+                    }
+                }
+                next.player = self.normalize(cell, &next);
+                out.push((next, cell, *dir));
+            }
+        }
+        out
+    }
+
+    /// Whether the crate of `color` at `cell` stands on a target of its
+    /// color.
+    pub(crate) fn on_target(&self, cell: usize, color: u8) -> bool {
+        match self.target_at[cell] {
+            Some(t) => self.targets[t].1 == color,
+            None => false,
+        }
+    }
+
+    /// Whether every target is taken or, under classic rules, covered.
+    fn solved(&self, node: &Node, all: u64) -> bool {
+        if self.classic {
+            node.crates
+                .iter()
+                .filter(|c| self.on_target(c.0, c.1))
+                .count()
+                == self.targets.len()
+        } else {
+            node.done == all
+        }
+    }
+
+    pub(crate) fn position(&self, cell: usize) -> Position {
+        Position::new((cell % self.width) as i32, (cell / self.width) as i32)
+    }
+}
+
+/// Finds the solution with the fewest pushes from the current position of
+/// `state`, giving up after looking at `limit` positions. Boards with
+/// special tiles aren't searched.
+pub fn solve(state: &GameState, limit: usize) -> Result<Solution, SolveError> {
+    if state.has_special_tiles() {
+        return Err(SolveError::SpecialTiles);
+    }
+    let (board, crates, player) = Board::new(state);
+    if board.targets.len() > 64 {
+        return Err(SolveError::TooManyTargets);
+    }
+    let all = if board.targets.len() == 64 {
+        u64::MAX
+    } else {
+        (1u64 << board.targets.len()) - 1
+    };
+    let mut start = Node {
+        crates: crates,
+        done: 0,
+        player: player,
+    };
+    start.player = board.normalize(player, &start);
+
+    let mut nodes: Vec<Node> = vec![start.clone()];
+    let mut parent: Vec<Option<(usize, usize, Direction)>> = vec![None];
+    let mut cost: Vec<u32> = vec![0];
+    let mut index: HashMap<Node, usize> = HashMap::new();
+    index.insert(start.clone(), 0);
+    let mut open = BinaryHeap::new();
+    let h = board.bound(&start);
+    if h == INF {
+        return Err(SolveError::NotFound);
+    }
+    open.push(Reverse((h, h, 0, 0usize)));
+
+    let mut found = None;
+    let mut expanded = 0;
+    while let Some(Reverse((_, _, g, id))) = open.pop() {
+        if g > cost[id] {
+            // reached again with fewer pushes since it was queued
+            continue;
+        }
+        if board.solved(&nodes[id], all) {
+            found = Some(id);
+            break;
+        }
+        expanded += 1;
+        if expanded > limit {
+            return Err(SolveError::NotFound);
+        }
+        let g = cost[id] + 1;
+        for (next, cell, dir) in board.pushes(&nodes[id]) {
+            let known = index.get(&next).cloned();
+            let next_id = match known {
+                Some(k) if cost[k] <= g => continue,
+                Some(k) => k,
+                None => {
+                    nodes.push(next.clone());
+                    parent.push(None);
+                    cost.push(INF);
+                    index.insert(next, nodes.len() - 1);
+                    nodes.len() - 1
+                }
+            };
+            let h = board.bound(&nodes[next_id]);
+            if h == INF {
+                continue;
+            }
+            cost[next_id] = g;
+            parent[next_id] = Some((id, cell, dir));
+            open.push(Reverse((g + h, h, g, next_id)));
+        }
+    }
+
+    let mut pushes = Vec::new();
+    let mut id = found.ok_or(SolveError::NotFound)?;
+    while let Some((prev, cell, dir)) = parent[id] {
+        pushes.push(Push {
+            position: board.position(cell),
+            direction: dir,
+        });
+        id = prev;
+    }
+    pushes.reverse();
+    let moves = board.moves(&start, player, &pushes);
+    Ok(Solution {
+        pushes: pushes,
+        moves: moves,
+    })
+}
+
+impl Board {
+    /// Key presses playing `pushes` from the player at `player`.
+    fn moves(&self, start: &Node, player: usize, pushes: &[Push]) -> Vec<Direction> {
+        let mut node = start.clone();
+        let mut player = player;
+        let mut moves = Vec::new();
+        for push in pushes.iter() {
+            let cell = push.position.get_y() as usize * self.width + push.position.get_x() as usize;
+            let spot = self.back(cell, push.direction).unwrap();
+            let came = self.reach(player, &node);
+            let mut walk = Vec::new();
+            let mut at = spot;
+            while at != player {
+                let (prev, dir) = came[at].unwrap();
+                walk.push(dir);
+                at = prev;
+            }
+            walk.reverse();
+            moves.extend(walk);
+            moves.push(push.direction);
+
+            let to = self.step(cell, push.direction).unwrap();
+            let i = node.crates.iter().position(|c| c.0 == cell).unwrap();
+            match self.target_at[to] {
+                Some(t) if !self.classic => {
+                    node.done |= 1 << t;
+                    node.crates.remove(i);
+                }
+                _ => node.crates[i].0 = to,
+            }
+            player = cell;
+        }
+        moves
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use level::Level;
+
+    fn load(text: &str) -> GameState {
+        let mut state = GameState::new((15, 10), Vec::new());
+        state.load_level(&Level::parse(text).unwrap());
+        state
+    }
+
+    #[test]
+    fn finds_the_fewest_pushes() {
+        let mut state = load("########\n#@ $  .#\n#      #\n########\n");
+        let solution = solve(&state, 10_000).unwrap();
+        assert_eq!(solution.pushes.len(), 3);
+        let (last, moves) = solution.moves.split_last().unwrap();
+        for dir in moves {
+            state.move_player(*dir);
+        }
+        assert_eq!(state.targets_left, 1);
+        assert_eq!(*last, Direction::Right);
+    }
+
+    #[test]
+    fn walks_around_to_push_from_the_other_side() {
+        let state = load("#######\n#.@$  #\n#     #\n#######\n");
+        let solution = solve(&state, 10_000).unwrap();
+        assert_eq!(solution.pushes.len(), 2);
+        assert!(solution
+            .pushes
+            .iter()
+            .all(|p| p.direction == Direction::Left));
+        assert_eq!(solution.moves[0], Direction::Down);
+    }
+
+    #[test]
+    fn classic_rules_let_crates_pass_over_targets() {
+        let text = "######\n#@$..#\n# #$##\n#    #\n######\n";
+        let mut state = GameState::new((15, 10), Vec::new());
+        state.load_level(&Level::parse(text).unwrap());
+        assert_eq!(solve(&state, 10_000).err(), Some(SolveError::NotFound));
+        state.rule_set = RuleSet::Classic;
+        state.load_level(&Level::parse(text).unwrap());
+        let solution = solve(&state, 10_000).unwrap();
+        for dir in solution.moves {
+            state.move_player(dir);
+        }
+        assert!(state.is_solved());
+    }
+
+    #[test]
+    fn gives_up_on_a_crate_in_a_corner() {
+        let state = load("######\n#$  .#\n#@   #\n######\n");
+        assert_eq!(solve(&state, 10_000).err(), Some(SolveError::NotFound));
+    }
+
+    #[test]
+    fn leaves_special_tiles_alone() {
+        let state = load("######\n#@$~.#\n######\n");
+        assert_eq!(solve(&state, 10_000).err(), Some(SolveError::SpecialTiles));
+    }
+}
@@ -0,0 +1,1047 @@
+use std::fmt;
+use std::time::SystemTime;
+
+use deadlock::{self, Deadlock};
+use generator::{generate, GeneratorConfig, Metrics};
+use history::{History, Snapshot};
+use level::{Level, Tile, DEFAULT_COLOR};
+use na::core::DMatrix;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use scoring::ScoringRules;
+use structs::*;
+use tiles::{self, Behavior};
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum ObjectType {
+    Blocking,
+    Passing,
+    Crate,
+    Target,
+}
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+/// What happens to a crate pushed onto a target of its color.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum RuleSet {
+    /// It locks in place and the target is used up.
+    Locking,
+    /// It can be pushed off again, as in classic Sokoban. Crates pass over
+    /// targets of other colors.
+    Classic,
+}
+/// What a step did, a push moves the player into the crate's place.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Move {
+    Blocked,
+    Walk,
+    Push,
+}
+#[derive(Clone)]
+pub struct Player {
+    pub position: Position,
+    pub facing: Direction,
+}
+/// A tile or piece on the board. `sprite` holds the texture key only
+/// ("wall", "floor" or a crate color such as "red.png"), front ends map it
+/// to whatever they draw with.
+#[derive(Clone)]
+pub struct Object {
+    pub sprite: Option<String>,
+    pub obj_type: ObjectType,
+    pub position: Position,
+}
+pub struct CollisionMatrix {
+    pub coll: DMatrix<bool>,
+}
+/// The usual Sokoban counters. A box line is a run of pushes of one crate
+/// in one direction, a box change a push of another crate than the last.
+#[derive(Clone, Debug)]
+pub struct Score {
+    /// Every step, pushes included.
+    pub moves: i32,
+    pub pushes: i32,
+    pub box_lines: i32,
+    pub box_changes: i32,
+    /// Crates brought onto their targets. Under classic rules the ones
+    /// pushed off again are taken back, so it can go below zero.
+    pub scored: i32,
+    /// Points earned by the crates scored, before penalties.
+    pub crate_points: i32,
+    /// Where the last pushed crate ended up and the way it went.
+    last_push: Option<(Position, Direction)>,
+}
+/// Everything needed to play a level, with no window or textures attached.
+pub struct GameState {
+    pub floor: Vec<Object>,
+    pub special: Vec<Object>,
+    pub player: Player,
+    pub start_t: SystemTime,
+    pub score: Score,
+    pub rules: ScoringRules,
+    /// Rules levels are loaded and played with.
+    pub rule_set: RuleSet,
+    /// Any crate fits any target, levels are loaded and generated in
+    /// `DEFAULT_COLOR` only.
+    pub monochrome: bool,
+    pub targets_left: i32,
+    /// Width and height of the board being played.
+    pub dimensions: (usize, usize),
+    /// Width and height of generated levels.
+    pub size: (u32, u32),
+    /// Crate colors levels are generated with, e.g. "red.png".
+    pub colors: Vec<String>,
+    pub generator: GeneratorConfig,
+    pub history: History,
+    /// Set when the position can't be solved anymore, updated after every
+    /// move.
+    pub deadlock: Option<Deadlock>,
+    /// The level as it was loaded or generated.
+    pub level: Option<Level>,
+    /// Seed the level was generated from, kept in its `Seed:` line.
+    pub seed: Option<u64>,
+    /// Moves made in LURD notation, may run past `lurd_len` after an undo
+    /// so they can be redone.
+    lurd: String,
+    lurd_len: usize,
+}
+impl Object {
+    pub fn new(position: Position, obj_type: ObjectType, sprite: Option<String>) -> Object {
+        Object {
+            position: position,
+            obj_type: obj_type,
+            sprite: sprite,
+        }
+    }
+}
+
+impl Direction {
+    pub fn offset(&self) -> Position {
+        match *self {
+            Direction::Down => Position::new(0, 1),
+            Direction::Up => Position::new(0, -1),
+            Direction::Left => Position::new(-1, 0),
+            Direction::Right => Position::new(1, 0),
+        }
+    }
+}
+
+/// `dir` in LURD notation.
+pub fn lurd_char(dir: Direction, push: bool) -> char {
+    let c = match dir {
+        Direction::Left => 'l',
+        Direction::Up => 'u',
+        Direction::Right => 'r',
+        Direction::Down => 'd',
+    };
+    if push {
+        c.to_ascii_uppercase()
+    } else {
+        c
+    }
+}
+
+impl CollisionMatrix {
+    /// Whether the cell `x`, `y` away from `ind` (row, column) is blocked.
+    /// Cells off the matrix aren't.
+    pub fn next(&self, ind: (usize, usize), x: i32, y: i32) -> bool {
+        let row = ind.0 as i32 + y;
+        let col = ind.1 as i32 + x;
+        if row < 0
+            || col < 0
+            || row as usize >= self.coll.nrows()
+            || col as usize >= self.coll.ncols()
+        {
+            false
+        } else {
+            self.coll[(row as usize, col as usize)]
+        }
+    }
+}
+impl Score {
+    /// Counts a push of the crate at `from` towards `dir`.
+    fn push(&mut self, from: Position, dir: Direction) {
+        self.pushes += 1;
+        match self.last_push {
+            Some((pos, d)) if pos == from && d == dir => (),
+            Some((pos, _)) if pos == from => self.box_lines += 1,
+            _ => {
+                self.box_lines += 1;
+                self.box_changes += 1;
+            }
+        }
+        self.last_push = Some((from + dir.offset(), dir));
+    }
+
+    pub fn new() -> Score {
+        Score {
+            moves: 0,
+            pushes: 0,
+            box_lines: 0,
+            box_changes: 0,
+            scored: 0,
+            crate_points: 0,
+            last_push: None,
+        }
+    }
+}
+
+impl Default for Score {
+    fn default() -> Score {
+        Score::new()
+    }
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} moves, {} pushes, {} box lines, {} box changes",
+            self.moves, self.pushes, self.box_lines, self.box_changes
+        )
+    }
+}
+
+impl GameState {
+    pub fn new(size: (usize, usize), mut colors: Vec<String>) -> GameState {
+        // seeds shouldn't depend on the order the colors were listed in
+        colors.sort();
+        let player = Player {
+            position: Position::new(1, 3),
+            facing: Direction::Up,
+        };
+        GameState {
+            floor: Vec::new(),
+            special: Vec::new(),
+            player: player,
+            start_t: SystemTime::now(),
+            score: Score::new(),
+            rules: ScoringRules::default(),
+            rule_set: RuleSet::Locking,
+            monochrome: false,
+            targets_left: -1,
+            dimensions: (0, 0),
+            size: (size.0 as u32, size.1 as u32),
+            colors: colors,
+            generator: GeneratorConfig::default(),
+            history: History::new(),
+            deadlock: None,
+            level: None,
+            seed: None,
+            lurd: String::new(),
+            lurd_len: 0,
+        }
+    }
+    pub fn move_player(&mut self, dir: Direction) -> Move {
+        let pos = self.player.position;
+        let before = self.snapshot();
+        let moved = self.check(&pos, dir);
+        self.player.facing = dir;
+        if moved != Move::Blocked {
+            self.history.record(before);
+            self.deadlock = deadlock::find(self);
+            self.lurd.truncate(self.lurd_len);
+            self.lurd.push(lurd_char(dir, moved == Move::Push));
+            self.lurd_len += 1;
+        }
+        moved
+    }
+
+    /// Moves made on the level so far, lowercase walks and uppercase
+    /// pushes. Undone moves are left out.
+    pub fn lurd(&self) -> &str {
+        &self.lurd[..self.lurd_len]
+    }
+
+    /// Whether every target has its crate. What comes next is up to the
+    /// front end.
+    pub fn is_solved(&self) -> bool {
+        self.targets_left == 0
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            floor: self.floor.clone(),
+            special: self.special.clone(),
+            player: self.player.clone(),
+            score: self.score.clone(),
+            targets_left: self.targets_left,
+            lurd_len: self.lurd_len,
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.floor = snapshot.floor;
+        self.special = snapshot.special;
+        self.player = snapshot.player;
+        self.score = snapshot.score;
+        self.targets_left = snapshot.targets_left;
+        self.lurd_len = snapshot.lurd_len;
+        self.deadlock = deadlock::find(self);
+    }
+
+    /// Takes back the last move, returns whether there was one.
+    pub fn undo(&mut self) -> bool {
+        let current = self.snapshot();
+        match self.history.undo(current) {
+            Some(prev) => {
+                self.restore(prev);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn redo(&mut self) -> bool {
+        let current = self.snapshot();
+        match self.history.redo(current) {
+            Some(next) => {
+                self.restore(next);
+                true
+            }
+            None => false,
+        }
+    }
+    /// Steps the player from `position` towards `dir`, pushing a crate if
+    /// there is one. The tiles stepped and pushed onto get their say
+    /// through their `Behavior`.
+    pub fn check(&mut self, position: &Position, dir: Direction) -> Move {
+        let next = *position + dir.offset();
+        if !next.is_within(self.dimensions) || !self.lets_player(next, dir) {
+            return Move::Blocked;
+        }
+        let crate_found = {
+            let obj = self
+                .special
+                .iter()
+                .enumerate()
+                .filter(|x| !(x.1.obj_type == ObjectType::Target))
+                .find(|x| x.1.position == next);
+            match obj {
+                None => {
+                    self.player.position = next;
+                    self.score.moves += 1;
+                    self.player_lands(dir);
+                    return Move::Walk;
+                }
+                Some(ele) if ele.1.obj_type == ObjectType::Crate => ele.0,
+                Some(_) => return Move::Blocked,
+            }
+        };
+        let beyond = next + dir.offset();
+        let target_found = match self.crate_entry(crate_found, beyond, dir) {
+            Some(target) => target,
+            None => return Move::Blocked,
+        };
+        self.player.position = next;
+        self.score.moves += 1;
+        self.score.push(next, dir);
+        if !self.move_crate(crate_found, beyond, target_found) {
+            self.crate_lands(crate_found, dir);
+        }
+        self.player_lands(dir);
+        Move::Push
+    }
+
+    /// The floor tile at `pos`, None off the floor.
+    pub fn floor_at(&self, pos: Position) -> Option<&Object> {
+        self.floor.iter().find(|o| o.position == pos)
+    }
+
+    /// How the floor at `pos` behaves, None for plain floor.
+    fn behavior(&self, pos: Position) -> Option<&'static dyn Behavior> {
+        self.floor_at(pos).and_then(tiles::of)
+    }
+
+    /// Whether the board has any special tile.
+    pub fn has_special_tiles(&self) -> bool {
+        self.floor.iter().any(|o| tiles::of(o).is_some())
+    }
+
+    fn lets_player(&self, pos: Position, dir: Direction) -> bool {
+        self.behavior(pos).map_or(true, |b| b.lets_player(dir))
+    }
+
+    /// Whether anything but a target stands at `pos`.
+    pub fn occupied(&self, pos: Position) -> bool {
+        self.special
+            .iter()
+            .any(|o| o.position == pos && o.obj_type != ObjectType::Target)
+    }
+
+    /// Whether crate `i` can go onto `to` heading `dir`, and if so the
+    /// target of its color there. Targets of other colors stop it unless
+    /// playing by classic rules.
+    fn crate_entry(&self, i: usize, to: Position, dir: Direction) -> Option<Option<usize>> {
+        if !to.is_within(self.dimensions) || !self.behavior(to).map_or(true, |b| b.lets_crate(dir))
+        {
+            return None;
+        }
+        let classic = self.rule_set == RuleSet::Classic;
+        let color = self.special[i].sprite.as_ref();
+        let mut target = None;
+        for (j, obj) in self.special.iter().enumerate() {
+            if obj.position != to {
+                continue;
+            }
+            match obj.obj_type {
+                ObjectType::Target if obj.sprite.as_ref() == color => target = Some(j),
+                ObjectType::Target if classic => (),
+                _ => return None,
+            }
+        }
+        Some(target)
+    }
+
+    /// Puts crate `i` on `to`, scoring it on `target`. Returns whether it
+    /// locked there, which leaves `i` pointing elsewhere.
+    fn move_crate(&mut self, i: usize, to: Position, target: Option<usize>) -> bool {
+        let from = self.special[i].position;
+        let crate_type = self.special[i].sprite.clone().unwrap_or_default();
+        let left_target = self.has_target(from, &crate_type);
+        self.special[i].position = to;
+        if self.rule_set == RuleSet::Classic {
+            if left_target {
+                self.score.scored -= 1;
+                self.score.crate_points -= self.rules.crate_value(&crate_type);
+                self.targets_left += 1;
+            }
+            if target.is_some() {
+                self.score.scored += 1;
+                self.score.crate_points += self.rules.crate_value(&crate_type);
+                self.targets_left -= 1;
+            }
+            return false;
+        }
+        match target {
+            Some(target) => {
+                self.special[i].obj_type = ObjectType::Blocking;
+                self.score.scored += 1;
+                self.score.crate_points += self.rules.crate_value(&crate_type);
+                self.targets_left -= 1;
+                self.special.remove(target);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Where the player or a crate on the tile at `pos` comes out, the
+    /// next tile of the same kind in reading order when `pos` is first of
+    /// its pair, the one before otherwise.
+    pub fn partner(&self, pos: Position) -> Option<Position> {
+        let sprite = self.floor_at(pos)?.sprite.as_ref();
+        let pair: Vec<Position> = self
+            .floor
+            .iter()
+            .filter(|o| o.sprite.as_ref() == sprite)
+            .map(|o| o.position)
+            .collect();
+        let at = pair.iter().position(|p| *p == pos)?;
+        pair.get(at ^ 1).cloned()
+    }
+
+    /// Lets the tile under crate `i`, pushed towards `dir`, act on it.
+    pub fn crate_lands(&mut self, i: usize, dir: Direction) {
+        if let Some(tile) = self.behavior(self.special[i].position) {
+            tile.crate_lands(self, i, dir);
+        }
+    }
+
+    /// Moves crate `i` on to `to` heading `dir`, for tiles that carry
+    /// crates. Returns whether it got there and is still loose, nothing
+    /// moves if something stops it.
+    pub fn carry_crate(&mut self, i: usize, to: Position, dir: Direction) -> bool {
+        let target = match self.crate_entry(i, to, dir) {
+            Some(target) => target,
+            None => return false,
+        };
+        let locked = self.move_crate(i, to, target);
+        self.score.last_push = Some((to, dir));
+        !locked
+    }
+
+    /// Lets the tile the player stepped on act on them.
+    fn player_lands(&mut self, dir: Direction) {
+        if let Some(tile) = self.behavior(self.player.position) {
+            tile.player_lands(self, dir);
+        }
+    }
+
+    /// Where the last pushed crate came to rest, ice and teleporters
+    /// included.
+    pub fn last_pushed(&self) -> Option<Position> {
+        self.score.last_push.map(|p| p.0)
+    }
+
+    /// Whether there's a target of `color` at `pos`.
+    fn has_target(&self, pos: Position, color: &str) -> bool {
+        self.special.iter().any(|o| {
+            o.position == pos
+                && o.obj_type == ObjectType::Target
+                && o.sprite.as_deref() == Some(color)
+        })
+    }
+
+    /// Whether `obj` is a crate resting on a target of its color, locked
+    /// or, under classic rules, still loose.
+    pub fn placed(&self, obj: &Object) -> bool {
+        match obj.obj_type {
+            ObjectType::Blocking => obj.sprite.as_deref() != Some("wall"),
+            ObjectType::Crate => match obj.sprite {
+                Some(ref color) => self.has_target(obj.position, color),
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// The score so far under `rules`, never below zero. Par bonuses are
+    /// added once the level is solved.
+    pub fn points(&self) -> i32 {
+        let rules = &self.rules;
+        let time = SystemTime::now()
+            .duration_since(self.start_t)
+            .map(|t| t.as_secs())
+            .unwrap_or(0) as i32;
+        let mut points = self.score.crate_points
+            - self.score.moves * rules.move_penalty
+            - self.score.pushes * rules.push_penalty
+            - time * rules.time_penalty;
+        if let (true, Some(level)) = (self.is_solved(), self.level.as_ref()) {
+            points += rules.par_bonus(level, self.score.moves, self.score.pushes);
+        }
+        points.max(0)
+    }
+
+    /// Replaces the board with `level`. Crates already on a goal start out
+    /// locked, unless playing by classic rules.
+    pub fn load_level(&mut self, level: &Level) {
+        self.set_board(level);
+        self.start_counting();
+    }
+
+    /// Puts the level back as it was loaded, targets re-spawned and crates
+    /// unlocked, and starts counting again.
+    pub fn restart(&mut self) {
+        if let Some(level) = self.level.clone() {
+            self.load_level(&level);
+        }
+    }
+
+    /// Sets up the board of `level`, kept without its colors in
+    /// monochrome.
+    fn set_board(&mut self, level: &Level) {
+        let level = &if self.monochrome {
+            level.monochrome()
+        } else {
+            level.clone()
+        };
+        self.special.clear();
+        self.floor.clear();
+        self.dimensions = (level.width(), level.height());
+        self.level = Some(level.clone());
+        self.seed = level.meta("Seed").and_then(|s| s.parse().ok());
+        let mut crates = 0;
+        let mut goals = 0;
+        let mut paints = 0;
+        for y in 0..level.height() {
+            for x in 0..level.width() {
+                let pos = Position::new(x as i32, y as i32);
+                let tile = level.get(x, y);
+                match tile {
+                    Tile::Outside => continue,
+                    Tile::Wall => {
+                        self.special.push(Object::new(
+                            pos,
+                            ObjectType::Blocking,
+                            Some("wall".to_string()),
+                        ));
+                        continue;
+                    }
+                    Tile::Special(symbol) => {
+                        let sprite = match tiles::by_symbol(symbol) {
+                            Some(kind) if kind.colored() => {
+                                paints += 1;
+                                tiles::colored(kind, level.paint_color(paints - 1))
+                            }
+                            Some(kind) => kind.sprite().to_string(),
+                            None => "floor".to_string(),
+                        };
+                        self.floor
+                            .push(Object::new(pos, ObjectType::Passing, Some(sprite)));
+                        continue;
+                    }
+                    _ => self.floor.push(Object::new(
+                        pos,
+                        ObjectType::Passing,
+                        Some("floor".to_string()),
+                    )),
+                }
+                if tile.is_crate() {
+                    let locked = tile.is_goal() && self.rule_set == RuleSet::Locking;
+                    if tile.is_goal() && !locked {
+                        // the target stays under the crate
+                        let color = level.goal_color(goals).to_string();
+                        self.special
+                            .push(Object::new(pos, ObjectType::Target, Some(color)));
+                    }
+                    let obj_type = if locked {
+                        ObjectType::Blocking
+                    } else {
+                        ObjectType::Crate
+                    };
+                    let color = level.crate_color(crates).to_string();
+                    self.special.push(Object::new(pos, obj_type, Some(color)));
+                    crates += 1;
+                } else if tile.is_goal() {
+                    let color = level.goal_color(goals).to_string();
+                    self.special
+                        .push(Object::new(pos, ObjectType::Target, Some(color)));
+                }
+                if tile.is_goal() {
+                    goals += 1;
+                }
+                if tile == Tile::Player || tile == Tile::PlayerOnGoal {
+                    self.player.position = pos;
+                }
+            }
+        }
+        let filled = self.special.iter().filter(|o| self.placed(o)).count();
+        self.targets_left = self
+            .special
+            .iter()
+            .filter(|o| o.obj_type == ObjectType::Target)
+            .count() as i32;
+        if self.rule_set == RuleSet::Classic {
+            self.targets_left -= filled as i32;
+        }
+        self.deadlock = deadlock::find(self);
+    }
+
+    /// The current board as a level, locked crates and crates standing on
+    /// a target are written as crates on a goal. Special tiles under a
+    /// crate or the player can't be written and are left out. Colors are
+    /// only written when some crate or paint isn't `DEFAULT_COLOR`, so
+    /// plain levels stay plain XSB.
+    pub fn to_level(&self) -> Level {
+        let (width, height) = self.dimensions;
+        let mut tiles = vec![vec![Tile::Outside; width]; height];
+        let mut crate_colors = vec![vec![None; width]; height];
+        let mut goal_colors = vec![vec![None; width]; height];
+        let mut paint_colors = vec![vec![None; width]; height];
+        for obj in self.floor.iter() {
+            let (x, y) = (obj.position.get_x() as usize, obj.position.get_y() as usize);
+            tiles[y][x] = match tiles::of(obj) {
+                Some(behavior) => Tile::Special(behavior.symbol()),
+                None => Tile::Floor,
+            };
+            paint_colors[y][x] = tiles::color(obj).map(String::from);
+        }
+        for obj in self.special.iter() {
+            let (x, y) = (obj.position.get_x() as usize, obj.position.get_y() as usize);
+            let color = obj.sprite.clone();
+            // under classic rules a crate and its target share the cell
+            let under = tiles[y][x];
+            tiles[y][x] = match obj.obj_type {
+                ObjectType::Crate => {
+                    crate_colors[y][x] = color;
+                    if under == Tile::Goal {
+                        Tile::CrateOnGoal
+                    } else {
+                        Tile::Crate
+                    }
+                }
+                ObjectType::Target => {
+                    goal_colors[y][x] = color;
+                    if under == Tile::Crate {
+                        Tile::CrateOnGoal
+                    } else {
+                        Tile::Goal
+                    }
+                }
+                ObjectType::Passing => Tile::Floor,
+                ObjectType::Blocking => {
+                    if obj.sprite.as_deref() == Some("wall") {
+                        Tile::Wall
+                    } else {
+                        crate_colors[y][x] = color.clone();
+                        goal_colors[y][x] = color;
+                        Tile::CrateOnGoal
+                    }
+                }
+            };
+        }
+        {
+            let pos = &self.player.position;
+            let cell = &mut tiles[pos.get_y() as usize][pos.get_x() as usize];
+            *cell = if cell.is_goal() {
+                Tile::PlayerOnGoal
+            } else {
+                Tile::Player
+            };
+        }
+        // paint under a crate or the player isn't written
+        for (y, row) in tiles.iter().enumerate() {
+            for (x, tile) in row.iter().enumerate() {
+                if let Tile::Special(_) = *tile {
+                    continue;
+                }
+                paint_colors[y][x] = None;
+            }
+        }
+        let flatten = |grid: Vec<Vec<Option<String>>>| -> Vec<String> {
+            grid.into_iter().flatten().flatten().collect()
+        };
+        let crate_colors = flatten(crate_colors);
+        let goal_colors = flatten(goal_colors);
+        let paint_colors = flatten(paint_colors);
+        let colored = crate_colors
+            .iter()
+            .chain(goal_colors.iter())
+            .any(|c| c != DEFAULT_COLOR);
+        let painted = paint_colors.iter().any(|c| c != DEFAULT_COLOR);
+        Level {
+            title: None,
+            notes: Vec::new(),
+            tiles: tiles,
+            crate_colors: if colored { crate_colors } else { Vec::new() },
+            goal_colors: if colored { goal_colors } else { Vec::new() },
+            paint_colors: if painted { paint_colors } else { Vec::new() },
+        }
+    }
+
+    /// Generates a level from a random seed.
+    pub fn gen_level(&mut self) -> Metrics {
+        let seed = rand::thread_rng().gen();
+        self.gen_seeded(seed)
+    }
+
+    /// Generates the level of `seed`. The same seed, size, colors and
+    /// generator settings always give the same level, in monochrome with
+    /// its colors dropped.
+    pub fn gen_seeded(&mut self, seed: u64) -> Metrics {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let (mut level, metrics) = generate(self.size, &self.colors, &self.generator, &mut rng);
+        level.notes.push(format!("Seed: {}", seed));
+        self.set_board(&level);
+        self.start_counting();
+        metrics
+    }
+
+    /// Forgets the moves, score and time of the level played before.
+    fn start_counting(&mut self) {
+        self.history.clear();
+        self.lurd.clear();
+        self.lurd_len = 0;
+        self.score = Score::new();
+        self.start_t = SystemTime::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A room with the player at (1, 1) and `objects` colored "red.png".
+    fn room(objects: &[(i32, i32, ObjectType)]) -> GameState {
+        let mut state = GameState::new((15, 10), Vec::new());
+        state.dimensions = (6, 5);
+        state.player.position = Position::new(1, 1);
+        for &(x, y, obj_type) in objects {
+            let sprite = match obj_type {
+                ObjectType::Blocking => "wall",
+                _ => "red.png",
+            };
+            let pos = Position::new(x, y);
+            state
+                .special
+                .push(Object::new(pos, obj_type, Some(sprite.to_string())));
+        }
+        state.targets_left = objects.iter().filter(|o| o.2 == ObjectType::Target).count() as i32;
+        state
+    }
+
+    #[test]
+    fn crates_lock_on_their_target() {
+        let mut state = room(&[
+            (2, 1, ObjectType::Crate),
+            (3, 1, ObjectType::Target),
+            (1, 3, ObjectType::Target),
+        ]);
+        state.move_player(Direction::Right);
+        assert_eq!(state.special[0].position, Position::new(3, 1));
+        assert_eq!(state.special[0].obj_type, ObjectType::Blocking);
+        assert_eq!(state.special.len(), 2);
+        assert_eq!(state.targets_left, 1);
+        assert_eq!(state.score.scored, 1);
+    }
+
+    #[test]
+    fn levels_round_trip_through_the_state() {
+        let text = "#######\n#@$ . #\n# * ###\n####\n";
+        let mut state = GameState::new((15, 10), Vec::new());
+        state.load_level(&Level::parse(text).unwrap());
+        assert_eq!(state.targets_left, 1);
+        assert_eq!(state.to_level().to_xsb(), text);
+    }
+
+    #[test]
+    fn crate_colors_round_trip_through_the_state() {
+        let text = "#######\n#@$ . #\n# $ *.#\n#######\n\
+                    Crates: red.png blue.png green.png\nGoals: blue.png green.png red.png\n";
+        let mut state = GameState::new((15, 10), Vec::new());
+        state.load_level(&Level::parse(text).unwrap());
+        assert_eq!(state.targets_left, 2);
+        assert_eq!(state.to_level().to_xsb(), text);
+    }
+
+    #[test]
+    fn undo_and_redo_restore_locked_crates_and_targets() {
+        let text = "#######\n#@$.  #\n#   $.#\n#######\n";
+        let mut state = GameState::new((15, 10), Vec::new());
+        state.load_level(&Level::parse(text).unwrap());
+        state.move_player(Direction::Right);
+        state.move_player(Direction::Down);
+        assert_eq!(state.targets_left, 1);
+        let locked = state.to_level().to_xsb();
+        assert!(state.undo());
+        assert!(state.undo());
+        assert!(!state.undo());
+        assert_eq!(state.to_level().to_xsb(), text);
+        assert_eq!(state.targets_left, 2);
+        assert_eq!(state.score.scored, 0);
+        assert!(state.redo());
+        assert!(state.redo());
+        assert!(!state.redo());
+        assert_eq!(state.to_level().to_xsb(), locked);
+        assert_eq!(state.score.scored, 1);
+    }
+
+    #[test]
+    fn the_board_is_as_big_as_the_level() {
+        let mut state = GameState::new((40, 25), Vec::new());
+        state.load_level(&Level::parse("#@ $ .#\n").unwrap());
+        assert_eq!(state.dimensions, (7, 1));
+        state.move_player(Direction::Up);
+        assert_eq!(state.player.position, Position::new(1, 0));
+        state.gen_level();
+        assert_eq!(state.dimensions, (40, 25));
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_level() {
+        let colors = |names: &[&str]| names.iter().map(|c| c.to_string()).collect();
+        let mut a = GameState::new((12, 9), colors(&["red.png", "blue.png", "green.png"]));
+        let mut b = GameState::new((12, 9), colors(&["green.png", "red.png", "blue.png"]));
+        a.gen_seeded(42);
+        b.gen_seeded(42);
+        assert_eq!(a.seed, Some(42));
+        assert_eq!(a.to_level().to_xsb(), b.to_level().to_xsb());
+        assert!(a
+            .level
+            .as_ref()
+            .unwrap()
+            .notes
+            .contains(&"Seed: 42".to_string()));
+        b.gen_seeded(43);
+        assert_ne!(a.to_level().to_xsb(), b.to_level().to_xsb());
+    }
+
+    #[test]
+    fn pushes_of_one_crate_one_way_make_a_box_line() {
+        let text = "#######\n#     #\n#@$  .#\n#  $  #\n#   . #\n#######\n";
+        let mut state = GameState::new((15, 10), Vec::new());
+        state.load_level(&Level::parse(text).unwrap());
+        assert_eq!(state.move_player(Direction::Right), Move::Push);
+        assert_eq!(state.move_player(Direction::Right), Move::Push);
+        assert_eq!(
+            (
+                state.score.pushes,
+                state.score.box_lines,
+                state.score.box_changes
+            ),
+            (2, 1, 1)
+        );
+        assert_eq!(state.move_player(Direction::Down), Move::Push);
+        assert_eq!(
+            (
+                state.score.moves,
+                state.score.box_lines,
+                state.score.box_changes
+            ),
+            (3, 2, 2)
+        );
+        state.undo();
+        assert_eq!(
+            (
+                state.score.pushes,
+                state.score.box_lines,
+                state.score.box_changes
+            ),
+            (2, 1, 1)
+        );
+    }
+
+    #[test]
+    fn points_follow_the_scoring_rules() {
+        let mut state = GameState::new((15, 10), Vec::new());
+        state.rules =
+            ScoringRules::parse("crate = 10\nmove = 2\nsecond = 0\npar moves = 7").unwrap();
+        state.load_level(&Level::parse("#####\n#@$.#\n#####\nPar: 1 1\n").unwrap());
+        assert_eq!(state.points(), 0);
+        state.move_player(Direction::Right);
+        assert!(state.is_solved());
+        assert_eq!(state.points(), 10 - 2 + 7);
+        state.undo();
+        assert_eq!(state.points(), 0);
+    }
+
+    #[test]
+    fn restart_puts_the_level_back() {
+        let mut state = GameState::new((15, 10), Vec::new());
+        state.load_level(&Level::parse("#####\n#@$.#\n#####\n").unwrap());
+        state.move_player(Direction::Right);
+        assert!(state.is_solved());
+        state.restart();
+        assert_eq!(state.targets_left, 1);
+        assert_eq!(state.score.moves, 0);
+        assert_eq!(state.lurd(), "");
+        assert!(!state.undo());
+        state.gen_seeded(7);
+        let first = state.to_level().to_xsb();
+        state.move_player(Direction::Right);
+        state.restart();
+        assert_eq!(state.seed, Some(7));
+        assert_eq!(state.to_level().to_xsb(), first);
+    }
+
+    #[test]
+    fn classic_crates_can_leave_their_target() {
+        let mut state = GameState::new((15, 10), Vec::new());
+        state.rule_set = RuleSet::Classic;
+        state.load_level(&Level::parse("######\n#@$. #\n#  * #\n######\n").unwrap());
+        assert_eq!(state.targets_left, 1);
+        assert_eq!(state.move_player(Direction::Right), Move::Push);
+        assert!(state.is_solved());
+        assert_eq!(state.score.scored, 1);
+        assert_eq!(state.move_player(Direction::Right), Move::Push);
+        assert_eq!(state.targets_left, 1);
+        assert_eq!(state.score.scored, 0);
+        assert!(state.deadlock.is_some());
+        let text = state.to_level().to_xsb();
+        assert!(
+            text.contains("#  +$#") && text.contains("#  * #"),
+            "{}",
+            text
+        );
+        state.undo();
+        assert!(state.is_solved());
+        assert!(state.deadlock.is_none());
+    }
+
+    #[test]
+    fn monochrome_crates_fit_any_target() {
+        let text = "######\n#@$ .#\n######\nCrates: red.png\nGoals: blue.png\n";
+        let level = Level::parse(text).unwrap();
+        let mut state = GameState::new((15, 10), Vec::new());
+        state.load_level(&level);
+        state.move_player(Direction::Right);
+        state.move_player(Direction::Right);
+        assert!(!state.is_solved());
+        state.monochrome = true;
+        state.load_level(&level);
+        state.move_player(Direction::Right);
+        state.move_player(Direction::Right);
+        assert!(state.is_solved());
+        assert!(state.to_level().crate_colors.is_empty());
+    }
+
+    fn play(text: &str, moves: &[Direction]) -> GameState {
+        let mut state = GameState::new((15, 10), Vec::new());
+        state.load_level(&Level::parse(text).unwrap());
+        for dir in moves {
+            state.move_player(*dir);
+        }
+        state
+    }
+
+    #[test]
+    fn crates_slide_over_ice() {
+        let state = play("########\n#@$~~ .#\n########\n", &[Direction::Right]);
+        assert_eq!(state.last_pushed(), Some(Position::new(5, 1)));
+        assert_eq!(state.player.position, Position::new(2, 1));
+    }
+
+    #[test]
+    fn holes_swallow_a_crate_and_turn_to_floor() {
+        let text = "#######\n#@$o$.#\n#######\n";
+        let mut state = play(text, &[Direction::Right]);
+        assert_eq!(state.to_level().to_xsb(), "#######\n# @ $.#\n#######\n");
+        assert_eq!(state.move_player(Direction::Right), Move::Walk);
+        assert_eq!(state.move_player(Direction::Right), Move::Push);
+        assert!(state.is_solved());
+        assert!(state.undo() && state.undo() && state.undo());
+        assert_eq!(state.to_level().to_xsb(), text);
+    }
+
+    #[test]
+    fn one_way_floor_is_entered_only_its_way() {
+        let state = play(
+            "######\n#@$>.#\n######\n",
+            &[Direction::Right, Direction::Right],
+        );
+        assert!(state.is_solved());
+        let mut state = play("######\n#@$<.#\n######\n", &[]);
+        assert_eq!(state.move_player(Direction::Right), Move::Blocked);
+    }
+
+    #[test]
+    fn teleporters_send_the_player_to_their_partner() {
+        let mut state = play("########\n#@T#T$.#\n########\n", &[Direction::Right]);
+        assert_eq!(state.player.position, Position::new(4, 1));
+        assert_eq!(state.move_player(Direction::Right), Move::Push);
+        assert!(state.is_solved());
+    }
+
+    #[test]
+    fn paint_recolors_the_crates_pushed_onto_it() {
+        let text = "######\n#@$%.#\n######\nGoals: blue.png\nPaints: blue.png\n";
+        let mut state = play(text, &[Direction::Right]);
+        assert_eq!(state.to_level().crate_colors, vec!["blue.png".to_string()]);
+        state.move_player(Direction::Right);
+        assert!(state.is_solved());
+    }
+
+    #[test]
+    fn generating_forgets_the_level_played_before() {
+        let mut state = play("######\n#@$ .#\n######\n", &[Direction::Right]);
+        assert_eq!(state.score.pushes, 1);
+        state.gen_seeded(3);
+        assert_eq!(state.score.moves, 0);
+        assert_eq!(state.score.pushes, 0);
+        assert_eq!(state.lurd(), "");
+        assert!(!state.undo());
+    }
+
+    #[test]
+    fn walls_and_crates_in_a_row_block() {
+        let mut state = room(&[
+            (1, 0, ObjectType::Blocking),
+            (2, 1, ObjectType::Crate),
+            (3, 1, ObjectType::Crate),
+            (1, 3, ObjectType::Target),
+        ]);
+        state.move_player(Direction::Up);
+        state.move_player(Direction::Right);
+        assert_eq!(state.player.position, Position::new(1, 1));
+        assert_eq!(state.score.moves, 0);
+        state.move_player(Direction::Down);
+        assert_eq!(state.player.position, Position::new(1, 2));
+    }
+}
@@ -1,3 +1,5 @@
+use std::error::Error;
+use std::fmt;
 use std::ops::Add;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -6,6 +8,26 @@ pub struct Position {
     y: i32,
 }
 
+/// A position outside of a board of `size` (width, height).
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct OutOfBounds {
+    pub x: i32,
+    pub y: i32,
+    pub size: (usize, usize),
+}
+
+impl fmt::Display for OutOfBounds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "position ({}, {}) is outside of the {}x{} board",
+            self.x, self.y, self.size.0, self.size.1
+        )
+    }
+}
+
+impl Error for OutOfBounds {}
+
 impl Add for Position {
     type Output = Position;
 
@@ -18,15 +40,28 @@ impl Add for Position {
 }
 
 impl Position {
-    pub fn new(x: i32, y: i32) -> Option<Position> {
-        if x > -2 && y > -2 && x < 50 && y < 50 {
-            Some(Position { x: x, y: y })
+    pub fn new(x: i32, y: i32) -> Position {
+        Position { x: x, y: y }
+    }
+
+    /// A position on a board of `size` (width, height).
+    pub fn on_board(x: i32, y: i32, size: (usize, usize)) -> Result<Position, OutOfBounds> {
+        let pos = Position::new(x, y);
+        if pos.is_within(size) {
+            Ok(pos)
         } else {
-            println!("barraca");
-            None
+            Err(OutOfBounds {
+                x: x,
+                y: y,
+                size: size,
+            })
         }
     }
 
+    pub fn is_within(&self, size: (usize, usize)) -> bool {
+        self.x >= 0 && self.y >= 0 && (self.x as usize) < size.0 && (self.y as usize) < size.1
+    }
+
     pub fn get_x(&self) -> i32 {
         self.x
     }
@@ -0,0 +1,275 @@
+//! Floor tiles that do more than hold the player and crates.
+//!
+//! Every kind of tile is a `Behavior` listed in `KINDS`, which is all the
+//! loader and the front ends look at. A special tile lies in
+//! `GameState::floor` as a `Passing` object named after its sprite.
+//! `GameState::check` asks the behavior of the cells a move touches and
+//! hands the state to the tile a crate or the player lands on, so a new
+//! kind of tile is a new impl and an entry in `KINDS`.
+//!
+//! | Symbol | Tile |
+//! | --- | --- |
+//! | `~` | ice, crates slide over it until something stops them |
+//! | `<` `>` `^` `v` | one-way floor, entered only heading that way |
+//! | `o` | hole, swallows the first crate pushed in and turns to floor |
+//! | `T` | teleporter, paired with the next one in reading order |
+//! | `=` | only crates may go there |
+//! | `:` | only the player may go there |
+//! | `%` | paint, crates pushed onto it take its color |
+//!
+//! A level only says which tile each cell is, so crates and the player
+//! start off them. Tiles that come in colors are named `kind/color`, e.g.
+//! `paint/red.png`, their colors listed on the level's `Paints:` line.
+
+use state::{Direction, GameState, Object, ObjectType};
+
+pub trait Behavior: Sync {
+    /// Character standing for the tile in level files.
+    fn symbol(&self) -> char;
+    /// Texture key, the file under `assets/tiles` without `.png`. Tiles
+    /// that come in colors have a directory of them instead, with a file
+    /// per crate color.
+    fn sprite(&self) -> &'static str;
+    /// Whether the tile comes in crate colors.
+    fn colored(&self) -> bool {
+        false
+    }
+    /// Whether the player may step onto the tile heading `dir`.
+    fn lets_player(&self, _dir: Direction) -> bool {
+        true
+    }
+    /// Whether a crate may be pushed onto the tile heading `dir`.
+    fn lets_crate(&self, _dir: Direction) -> bool {
+        true
+    }
+    /// Acts on the player of `state`, who just stepped onto the tile
+    /// heading `dir`.
+    fn player_lands(&self, _state: &mut GameState, _dir: Direction) {}
+    /// Acts on crate `i` of `state`, just pushed onto the tile heading
+    /// `dir`.
+    fn crate_lands(&self, _state: &mut GameState, _i: usize, _dir: Direction) {}
+    /// Whether `player_lands` does anything. Routes for the mouse keep off
+    /// tiles that do.
+    fn acts_on_player(&self) -> bool {
+        false
+    }
+    /// Whether `crate_lands` does anything.
+    fn acts_on_crate(&self) -> bool {
+        false
+    }
+}
+
+pub struct Ice;
+pub struct OneWay(pub Direction);
+pub struct Hole;
+pub struct Teleporter;
+pub struct CrateOnly;
+pub struct PlayerOnly;
+pub struct Paint;
+
+impl Behavior for Ice {
+    fn symbol(&self) -> char {
+        '~'
+    }
+    fn sprite(&self) -> &'static str {
+        "ice"
+    }
+    /// Carries on the same way to the next cell, if it can.
+    fn crate_lands(&self, state: &mut GameState, i: usize, dir: Direction) {
+        let to = state.special[i].position + dir.offset();
+        if state.carry_crate(i, to, dir) {
+            state.crate_lands(i, dir);
+        }
+    }
+    fn acts_on_crate(&self) -> bool {
+        true
+    }
+}
+
+impl Behavior for OneWay {
+    fn symbol(&self) -> char {
+        match self.0 {
+            Direction::Up => '^',
+            Direction::Down => 'v',
+            Direction::Left => '<',
+            Direction::Right => '>',
+        }
+    }
+    fn sprite(&self) -> &'static str {
+        match self.0 {
+            Direction::Up => "one_way_up",
+            Direction::Down => "one_way_down",
+            Direction::Left => "one_way_left",
+            Direction::Right => "one_way_right",
+        }
+    }
+    fn lets_player(&self, dir: Direction) -> bool {
+        dir == self.0
+    }
+    fn lets_crate(&self, dir: Direction) -> bool {
+        dir == self.0
+    }
+}
+
+impl Behavior for Hole {
+    fn symbol(&self) -> char {
+        'o'
+    }
+    fn sprite(&self) -> &'static str {
+        "hole"
+    }
+    fn lets_player(&self, _dir: Direction) -> bool {
+        false
+    }
+    /// Swallows the crate and turns to floor.
+    fn crate_lands(&self, state: &mut GameState, i: usize, _dir: Direction) {
+        let pos = state.special.remove(i).position;
+        if let Some(hole) = state.floor.iter_mut().find(|o| o.position == pos) {
+            hole.sprite = Some("floor".to_string());
+        }
+    }
+    fn acts_on_crate(&self) -> bool {
+        true
+    }
+}
+
+impl Behavior for Teleporter {
+    fn symbol(&self) -> char {
+        'T'
+    }
+    fn sprite(&self) -> &'static str {
+        "teleporter"
+    }
+    /// Takes the player to the other tile of the pair, if it's free.
+    fn player_lands(&self, state: &mut GameState, _dir: Direction) {
+        match state.partner(state.player.position) {
+            Some(to) if !state.occupied(to) => state.player.position = to,
+            _ => (),
+        }
+    }
+    /// Takes the crate to the other tile of the pair, if it's free. It
+    /// stays there, or it would go straight back.
+    fn crate_lands(&self, state: &mut GameState, i: usize, dir: Direction) {
+        match state.partner(state.special[i].position) {
+            Some(to) if to != state.player.position => {
+                state.carry_crate(i, to, dir);
+            }
+            _ => (),
+        }
+    }
+    fn acts_on_player(&self) -> bool {
+        true
+    }
+    fn acts_on_crate(&self) -> bool {
+        true
+    }
+}
+
+impl Behavior for CrateOnly {
+    fn symbol(&self) -> char {
+        '='
+    }
+    fn sprite(&self) -> &'static str {
+        "crate_only"
+    }
+    fn lets_player(&self, _dir: Direction) -> bool {
+        false
+    }
+}
+
+impl Behavior for PlayerOnly {
+    fn symbol(&self) -> char {
+        ':'
+    }
+    fn sprite(&self) -> &'static str {
+        "player_only"
+    }
+    fn lets_crate(&self, _dir: Direction) -> bool {
+        false
+    }
+}
+
+impl Behavior for Paint {
+    fn symbol(&self) -> char {
+        '%'
+    }
+    fn sprite(&self) -> &'static str {
+        "paint"
+    }
+    fn colored(&self) -> bool {
+        true
+    }
+    /// Gives the crate the color of the tile.
+    fn crate_lands(&self, state: &mut GameState, i: usize, _dir: Direction) {
+        let color = state
+            .floor_at(state.special[i].position)
+            .and_then(color)
+            .map(String::from);
+        if color.is_some() {
+            state.special[i].sprite = color;
+        }
+    }
+    fn acts_on_crate(&self) -> bool {
+        true
+    }
+}
+
+/// Every kind of special tile.
+pub static KINDS: &[&dyn Behavior] = &[
+    &Ice,
+    &OneWay(Direction::Up),
+    &OneWay(Direction::Down),
+    &OneWay(Direction::Left),
+    &OneWay(Direction::Right),
+    &Hole,
+    &Teleporter,
+    &CrateOnly,
+    &PlayerOnly,
+    &Paint,
+];
+
+/// The tile written as `symbol` in level files.
+pub fn by_symbol(symbol: char) -> Option<&'static dyn Behavior> {
+    KINDS.iter().find(|k| k.symbol() == symbol).cloned()
+}
+
+/// The tile drawn with `sprite`, whatever its color.
+pub fn by_sprite(sprite: &str) -> Option<&'static dyn Behavior> {
+    let kind = sprite.split('/').next().unwrap_or(sprite);
+    KINDS.iter().find(|k| k.sprite() == kind).cloned()
+}
+
+/// Sprite of a `kind` of tile in `color`, e.g. "paint/red.png".
+pub fn colored(kind: &dyn Behavior, color: &str) -> String {
+    format!("{}/{}", kind.sprite(), color)
+}
+
+/// Color of the floor object `obj` if it's a tile that comes in colors.
+pub fn color(obj: &Object) -> Option<&str> {
+    of(obj)?;
+    obj.sprite.as_ref()?.split_once('/').map(|(_, color)| color)
+}
+
+/// How the floor object `obj` behaves, None for plain floor and anything
+/// that isn't floor.
+pub fn of(obj: &Object) -> Option<&'static dyn Behavior> {
+    match (obj.obj_type, obj.sprite.as_ref()) {
+        (ObjectType::Passing, Some(sprite)) => by_sprite(sprite),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_kind_is_found_by_its_symbol_and_sprite() {
+        for kind in KINDS.iter() {
+            assert_eq!(by_symbol(kind.symbol()).unwrap().sprite(), kind.sprite());
+            assert_eq!(by_sprite(kind.sprite()).unwrap().symbol(), kind.symbol());
+        }
+        assert!(by_symbol('#').is_none());
+        assert!(by_sprite("floor").is_none());
+    }
+}
@@ -0,0 +1,303 @@
+//! Terminal front end, for playing over SSH or without OpenGL. It takes
+//! the same options as the window and plays by the same rules, only the
+//! drawing is different.
+
+extern crate crossterm;
+extern crate sokoban;
+
+use std::env;
+use std::io::{self, Write};
+use std::process;
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::style::{
+    Attribute, Color, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor,
+};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue, Result};
+use sokoban::cli::{self, Command};
+use sokoban::commands;
+use sokoban::pack::{Progress, Session};
+use sokoban::solver::solve;
+use sokoban::state::{Direction, GameState, Object, ObjectType};
+use sokoban::structs::Position;
+use sokoban::tiles;
+
+const HELP: &str =
+    "arrows/wasd move  u undo  y redo  r restart  n/p next/previous  g generate  h hint  q quit";
+
+/// Terminal color of a crate color key.
+fn color(sprite: &Option<String>) -> Color {
+    match sprite.as_deref() {
+        Some("red.png") => Color::Red,
+        Some("blue.png") => Color::Blue,
+        Some("green.png") => Color::Green,
+        Some("brown.png") => Color::DarkYellow,
+        _ => Color::Magenta,
+    }
+}
+
+/// Two characters per tile, so the board keeps its proportions.
+fn cell(obj: Option<&Object>, stuck: bool, placed: bool) -> (&'static str, Color, Option<Color>) {
+    let obj = match obj {
+        Some(obj) => obj,
+        None => return ("  ", Color::Reset, None),
+    };
+    match obj.obj_type {
+        ObjectType::Blocking if obj.sprite.as_deref() == Some("wall") => {
+            ("██", Color::DarkGrey, None)
+        }
+        // a crate locked on its target
+        ObjectType::Blocking => ("[]", Color::Black, Some(color(&obj.sprite))),
+        // a loose one on its target, under classic rules
+        ObjectType::Crate if placed => ("[]", Color::Black, Some(color(&obj.sprite))),
+        ObjectType::Crate if stuck => ("{}", color(&obj.sprite), None),
+        ObjectType::Crate => ("[]", color(&obj.sprite), None),
+        ObjectType::Target => ("()", color(&obj.sprite), None),
+        ObjectType::Passing => ("  ", Color::Reset, None),
+    }
+}
+
+fn direction(code: KeyCode) -> Option<Direction> {
+    match code {
+        KeyCode::Up | KeyCode::Char('w') => Some(Direction::Up),
+        KeyCode::Down | KeyCode::Char('s') => Some(Direction::Down),
+        KeyCode::Left | KeyCode::Char('a') => Some(Direction::Left),
+        KeyCode::Right | KeyCode::Char('d') => Some(Direction::Right),
+        _ => None,
+    }
+}
+
+/// Puts the terminal back however the game ends.
+struct RawMode;
+
+impl RawMode {
+    fn enter() -> Result<RawMode> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, Hide)?;
+        Ok(RawMode)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+struct Tui {
+    state: GameState,
+    session: Session,
+    message: Option<String>,
+    solver_limit: usize,
+}
+
+impl Tui {
+    fn hint(&mut self) {
+        self.message = Some(match solve(&self.state, self.solver_limit) {
+            Ok(ref sol) if !sol.pushes.is_empty() => {
+                let push = &sol.pushes[0];
+                format!(
+                    "Hint: push the crate at ({}, {}) {:?}",
+                    push.position.get_x(),
+                    push.position.get_y(),
+                    push.direction
+                )
+            }
+            Ok(_) => "Hint: nothing left to push".to_string(),
+            Err(e) => format!("Hint: {}", e),
+        });
+    }
+
+    /// Handles a key, returns false to quit.
+    fn press(&mut self, key: KeyEvent) -> bool {
+        self.message = None;
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        if self.session.asking() {
+            let yes = key.code == KeyCode::Char('y');
+            self.session.answer_generate(yes, &mut self.state);
+            return true;
+        }
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('c') if ctrl => return false,
+            KeyCode::Char('u') | KeyCode::Char('z') => {
+                self.state.undo();
+            }
+            KeyCode::Char('y') => {
+                self.state.redo();
+            }
+            KeyCode::Char('h') => self.hint(),
+            KeyCode::Char('r') => self.state.restart(),
+            KeyCode::Char('n') => self.message = self.session.skip_level(1, &mut self.state),
+            KeyCode::Char('p') => self.message = self.session.skip_level(-1, &mut self.state),
+            KeyCode::Char('g') => self.message = Some(self.session.ask_generate()),
+            code => {
+                if let Some(dir) = direction(code) {
+                    self.state.move_player(dir);
+                    if self.state.is_solved() {
+                        self.message = self.session.next_level(&mut self.state);
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    fn draw<W: Write>(&self, out: &mut W) -> Result<()> {
+        let state = &self.state;
+        let (width, height) = state.dimensions;
+        let mut grid: Vec<Option<&Object>> = vec![None; width * height];
+        // crates standing on a target hide it
+        let is_target = |o: &&Object| o.obj_type == ObjectType::Target;
+        let objects = state
+            .floor
+            .iter()
+            .chain(state.special.iter().filter(is_target))
+            .chain(state.special.iter().filter(|o| !is_target(o)));
+        for obj in objects {
+            let pos = &obj.position;
+            grid[pos.get_y() as usize * width + pos.get_x() as usize] = Some(obj);
+        }
+        let stuck = |pos: &Position| match state.deadlock {
+            Some(ref d) => d.crates.contains(pos),
+            None => false,
+        };
+
+        queue!(out, Clear(ClearType::All))?;
+        for y in 0..height {
+            queue!(out, MoveTo(0, y as u16))?;
+            for x in 0..width {
+                let pos = Position::new(x as i32, y as i32);
+                if state.player.position == pos {
+                    queue!(
+                        out,
+                        SetAttribute(Attribute::Bold),
+                        SetForegroundColor(Color::White),
+                        Print("@@"),
+                        SetAttribute(Attribute::Reset)
+                    )?;
+                    continue;
+                }
+                let obj = grid[y * width + x];
+                if let Some(tile) = obj.and_then(tiles::of) {
+                    // special tiles by their symbol in level files
+                    let symbol = tile.symbol();
+                    let fg = obj
+                        .and_then(tiles::color)
+                        .map_or(Color::Cyan, |c| color(&Some(c.to_string())));
+                    queue!(
+                        out,
+                        SetForegroundColor(fg),
+                        Print(format!("{}{}", symbol, symbol)),
+                        ResetColor
+                    )?;
+                    continue;
+                }
+                let placed = obj.is_some_and(|o| state.placed(o));
+                let (text, fg, bg) = cell(obj, stuck(&pos), placed);
+                queue!(out, SetForegroundColor(fg))?;
+                if let Some(bg) = bg {
+                    queue!(out, SetBackgroundColor(bg))?;
+                }
+                queue!(out, Print(text), ResetColor)?;
+            }
+        }
+
+        let score = &state.score;
+        let mut status = format!(
+            "Score: {}  Moves: {}  Pushes: {}  Box lines: {}  Box changes: {}  Targets left: {}",
+            state.points(),
+            score.moves,
+            score.pushes,
+            score.box_lines,
+            score.box_changes,
+            state.targets_left
+        );
+        if let Some(ref pack) = self.session.pack {
+            status.push_str(&format!("  Level {}/{}", pack.index + 1, pack.levels.len()));
+        } else if let Some(seed) = state.seed {
+            status.push_str(&format!("  Seed {}", seed));
+        }
+        let mut lines = vec![status, HELP.to_string()];
+        if state.deadlock.is_some() {
+            lines.push("Deadlocked - undo or restart".to_string());
+        }
+        if let Some(ref message) = self.message {
+            lines.push(message.clone());
+        }
+        for (i, line) in lines.iter().enumerate() {
+            queue!(out, MoveTo(0, (height + 1 + i) as u16), Print(line))?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+}
+
+fn main() {
+    let opts = match cli::parse(env::args().skip(1)) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            process::exit(2);
+        }
+    };
+    match opts.command {
+        Command::Play => (),
+        Command::Help => return println!("{}", cli::USAGE),
+        _ => process::exit(commands::run(&opts)),
+    }
+
+    let mut state = commands::new_state(&opts);
+    state.rules = commands::rules(&opts).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    let mut tui = Tui {
+        state: state,
+        session: Session::new(Progress::load_default().unwrap_or_else(|e| {
+            eprintln!("could not read progress: {}", e);
+            Progress::new()
+        })),
+        message: None,
+        solver_limit: opts.limit,
+    };
+    if opts.replay.is_some() {
+        eprintln!("replays play in the window, `sokoban verify` checks them");
+        process::exit(2);
+    }
+    if opts.edit.is_some() {
+        eprintln!("the level editor runs in the window");
+        process::exit(2);
+    }
+    if opts.file.is_some() {
+        tui.message =
+            commands::open_file(&opts, &mut tui.session, &mut tui.state).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(1);
+            });
+    } else if let Some(seed) = opts.seed {
+        tui.state.gen_seeded(seed);
+    } else {
+        tui.state.gen_level();
+    }
+
+    let result = (|| -> Result<()> {
+        let _raw = RawMode::enter()?;
+        let mut out = io::stdout();
+        loop {
+            tui.draw(&mut out)?;
+            if let Event::Key(key) = event::read()? {
+                if !tui.press(key) {
+                    return Ok(());
+                }
+            }
+        }
+    })();
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}